@@ -1,21 +1,247 @@
 
+mod archive;
+mod build_stamp;
+mod cache;
+mod cdash;
+mod compile_db;
+mod compiler_cache;
+mod ctest;
+mod destroy;
+mod diagnostic;
+mod explain;
+mod fuzz;
+mod jobs;
+mod junit;
+mod local_fs;
+mod memcheck;
+mod metrics;
+mod ninja_deps;
+mod presets;
+mod script;
+mod split_debug;
+mod summary;
+mod tidy_baseline;
+mod tidy_cache;
+mod timeout;
+mod watch;
+
 use std::{
+    collections::{hash_map::RandomState, BTreeMap, BTreeSet},
     env,
-    fs::{File, remove_dir_all, create_dir_all},
-    io::{Write, BufRead},
-    path::Path,
-    process::Command,
+    ffi::OsStr,
+    fs::{self, File, remove_dir_all, create_dir_all},
+    hash::BuildHasher,
+    io::{self, Write, BufRead, BufReader, IsTerminal},
+    path::{self, Path},
+    process::{Command, Output, Stdio},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
 };
 use glob::glob;
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use regex::Regex;
+use crate::config;
+use diagnostic::Diagnostic;
+use summary::Steps;
+
+/// When compilers should color their diagnostics
+#[derive(ValueEnum, Clone, Debug, PartialEq)]
+pub enum ColorMode {
+    /// Color when this tool's output is a terminal, even if a step is captured
+    Auto,
+    /// Always color, for logs viewed with an ANSI aware pager
+    Always,
+    /// Never color
+    Never,
+}
+
+/// A runtime sanitizer to build with
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Sanitizer {
+    /// AddressSanitizer, which includes LeakSanitizer on Linux
+    Address,
+    /// UndefinedBehaviorSanitizer
+    Undefined,
+    /// ThreadSanitizer, can't be combined with address or leak
+    Thread,
+    /// Standalone LeakSanitizer
+    Leak,
+}
+
+impl Sanitizer {
+    fn flag(self) -> &'static str {
+        match self {
+            Sanitizer::Address => "address",
+            Sanitizer::Undefined => "undefined",
+            Sanitizer::Thread => "thread",
+            Sanitizer::Leak => "leak",
+        }
+    }
+
+    /// The runtime options variable that takes this sanitizer's suppressions.
+    /// AddressSanitizer's leak reports come from LeakSanitizer, which is what
+    /// suppressions are usually needed for.
+    fn options_var(self) -> &'static str {
+        match self {
+            Sanitizer::Address | Sanitizer::Leak => "LSAN_OPTIONS",
+            Sanitizer::Undefined => "UBSAN_OPTIONS",
+            Sanitizer::Thread => "TSAN_OPTIONS",
+        }
+    }
+}
+
+/// A compiler cache to launch the compilers through
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum CompilerCache {
+    Ccache,
+    Sccache,
+}
+
+impl CompilerCache {
+    fn program(self) -> &'static str {
+        match self {
+            CompilerCache::Ccache => "ccache",
+            CompilerCache::Sccache => "sccache",
+        }
+    }
+
+    /// The environment variable that points the tool at its cache.
+    fn dir_var(self) -> &'static str {
+        match self {
+            CompilerCache::Ccache => "CCACHE_DIR",
+            CompilerCache::Sccache => "SCCACHE_DIR",
+        }
+    }
+}
+
+/// Output format of the CTest report
+#[derive(ValueEnum, Clone, Debug, PartialEq)]
+pub enum TestFormat {
+    /// JUnit XML as written by CTest
+    Junit,
+    /// Test Anything Protocol, converted from the JUnit report
+    Tap,
+}
 
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
+// The flags that run the test step, for the ctest options to require.
+#[command(group = clap::ArgGroup::new("test_step").args(["test", "coverage", "ci"]).multiple(true))]
 pub struct CmakeVars {
+    /// Configure, build and test the way CI expects: JUnit report, output on
+    /// failure, stop at the first failing step, fail when there are no tests
+    /// and exit non-zero on failure
+    #[clap(long, action)]
+    ci: bool,
+
+    /// Retry a failed step up to COUNT times, may be repeated. Overrides the
+    /// [retries] section of cli_assist.toml for that step.
+    #[clap(long, value_name = "STEP=COUNT", value_parser = config::parse_retry)]
+    retry: Vec<(String, u32)>,
+
+    /// Run the steps, then run them again whenever a source, CMakeLists.txt
+    /// or .cmake file under REPO_ROOT changes, until interrupted
+    #[clap(long, action, conflicts_with = "ci")]
+    watch: bool,
+
+    /// Clear the terminal before each watch rebuild
+    #[clap(long, action, requires = "watch")]
+    watch_clear: bool,
+
+    /// Shell command to run once the run finishes, whether it passed or not,
+    /// e.g. to send a desktop or chat notification. It gets the outcome in
+    /// CLI_ASSIST_STATUS (success or failure), the first failed step in
+    /// CLI_ASSIST_FAILED_STEP (empty if no step failed), the wall time in
+    /// CLI_ASSIST_DURATION_SECS and the build directory in BUILD_DIR. Its
+    /// own failure is reported but doesn't change the result.
+    #[clap(long, value_name = "CMD")]
+    notify: Option<String>,
+
+    /// Print the steps the other flags will run, and why, then exit
+    #[clap(long, action)]
+    list_steps: bool,
+
+    /// Write the commands the other flags would run, with their resolved
+    /// arguments and environment, to PATH as a `set -e` shell script, then
+    /// exit. Steps only this tool implements, e.g. --tidy or --smoke-test,
+    /// are left as comments.
+    #[clap(long, value_name = "PATH", conflicts_with_all = ["list_steps", "watch"])]
+    emit_script: Option<String>,
+
+    /// Print a cache variable's value, type and help string from
+    /// CMakeCache.txt and whether the user or the project set it, then exit
+    #[clap(long, value_name = "NAME")]
+    explain_var: Option<String>,
+
+    /// Print the last JUnit test report as a table, then exit
+    #[clap(long, action)]
+    show_test_report: bool,
+
+    /// Write Ninja's recorded dependencies to BUILD_DIR/Logs/ninja-deps.txt,
+    /// print an input count per output (or the inputs of TARGET's objects),
+    /// then exit. Only for the Ninja generators.
+    #[clap(long, value_name = "TARGET", num_args = 0..=1, default_missing_value = "")]
+    dump_ninja_deps: Option<String>,
+
+    /// Cross-check each built object's quoted #include lines against the
+    /// dependencies Ninja recorded for it, print the targets that likely
+    /// won't rebuild when those headers change, then exit. Needs a previous
+    /// build and is a no-op for the other generators.
+    #[clap(long, action)]
+    depfile_check: bool,
+
+    /// Fail before any step if BUILD_DIR is on a network filesystem such as
+    /// NFS or SMB, only warn on FUSE mounts or when that can't be determined
+    /// (Linux and macOS)
+    #[clap(long, action)]
+    require_local_build_dir: bool,
+
     /// Destroy the CMake Build Directory
     #[clap(short, long, action)]
     destroy: bool,
 
+    /// Destroy without asking, even when the build directory looks wrong:
+    /// no CMakeCache.txt, a .git inside or over --destroy-max-size. Without
+    /// a terminal to ask on, such a directory is only destroyed with --force.
+    #[clap(long, action, requires = "destroy")]
+    force: bool,
+
+    /// Size in GiB above which --destroy asks before removing BUILD_DIR
+    #[clap(long, value_name = "GIB", default_value_t = 20, requires = "destroy")]
+    destroy_max_size: u64,
+
+    /// Skip configure and build on the already configured BUILD_DIR, failing if
+    /// it has no CMakeCache.txt, e.g. when CI restored it from an earlier stage
+    #[clap(long, action, conflicts_with_all = [
+        "configure", "clean", "destroy", "release", "graphviz", "feature_summary", "configure_werror",
+        "toolchain_profile", "sanitize", "install_rpath", "link_jobs", "compiler_cache", "prefix_path",
+        "module_path", "initial_cache", "version_from_git", "compiler_color", "fuzz",
+    ])]
+    assume_configured: bool,
+
+    /// Print the configure output's feature_summary() block at the end of the
+    /// run. The full configure output is kept in BUILD_DIR/Logs/configure.log.
+    #[clap(long, action)]
+    feature_summary: bool,
+
+    /// Fail the configure step on any CMake Warning, CMake Warning (dev) or
+    /// CMake Deprecation Warning. Enables -Wdev and -Wdeprecated.
+    #[clap(long, action)]
+    configure_werror: bool,
+
+    /// Kill the configure step, and whatever it started, when it runs longer
+    /// than SECONDS, e.g. on a hung FetchContent download
+    #[clap(long, value_name = "SECONDS")]
+    configure_timeout: Option<u64>,
+
+    /// Capture step output and suggest fixes for well known failures
+    #[clap(long, action)]
+    explain_failure: bool,
+
+    /// Clean the CMake Build Directory, runs before any other build step
+    #[clap(long, action)]
+    clean: bool,
+
     /// Configure the CMake Project
     #[clap(long, action)]
     configure: bool,
@@ -24,10 +250,152 @@ pub struct CmakeVars {
     #[clap(short, long, action)]
     build: bool,
 
+    /// Capture build output into the Logs directory while still printing it
+    #[clap(long, action)]
+    capture_build: bool,
+
+    /// Print warning counts per warning flag after a build
+    #[clap(long, action, requires = "capture_build")]
+    warnings_summary: bool,
+
     /// Execute CTest on the CMake Project
     #[clap(long, action)]
     test: bool,
 
+    /// Format of the CTest report
+    #[clap(long, value_enum, default_value_t = TestFormat::Junit)]
+    test_format: TestFormat,
+
+    /// Run the tests as a CTest Experimental dashboard and submit the results
+    /// to CDash. The project must include(CTest), and the tests run again
+    /// under CTest's dashboard mode if --test also ran them.
+    #[clap(long, action)]
+    submit: bool,
+
+    /// CDash submit URL, e.g. https://my.cdash.org/submit.php?project=App
+    /// [default: from CTestConfig.cmake]
+    #[clap(long, value_name = "URL", requires = "submit")]
+    cdash_url: Option<String>,
+
+    /// Run the test step as the CTest dashboard script FILE (`ctest -S FILE`)
+    /// instead. The script decides what runs and where results go, so no
+    /// JUnit report is written.
+    #[clap(long, value_name = "FILE", value_parser = existing_file, requires = "test_step", conflicts_with_all = [
+        "test_shard", "test_wrapper", "slowest_tests",
+    ])]
+    ctest_script: Option<String>,
+
+    /// Fail the test step when CTest finds no tests at all, e.g. because the
+    /// tests weren't built or testing wasn't enabled, instead of passing.
+    /// --ci implies it
+    #[clap(long, action, requires = "test_step")]
+    require_tests: bool,
+
+    /// Run the tests once per CTest label, each group with its own pass/fail
+    /// and JUnit report (report-label-LABEL.xml), then print a summary by
+    /// label. Tests without a label form the "unlabeled" group, tests with
+    /// several labels run in each of their groups. There is no single JUnit
+    /// report, so --summary-json can't be used with it.
+    #[clap(long, action, requires = "test_step",
+        conflicts_with_all = ["ctest_script", "test_shard", "test_wrapper", "summary_json"])]
+    test_by_label: bool,
+
+    /// Run up to N label groups at once, printing each group's output when
+    /// it finishes. Groups with tests that have several labels still run
+    /// one at a time. Each group logs to its own label-NAME.log, but the
+    /// groups share LastTest.log and CTestCostData.txt in Testing/Temporary,
+    /// which then only reflect part of the run
+    #[clap(long, value_name = "N", default_value_t = 1, requires = "test_by_label",
+        value_parser = clap::value_parser!(u32).range(1..))]
+    test_jobs: u32,
+
+    /// Only run shard INDEX of TOTAL of the tests, INDEX counts from 1
+    #[clap(long, value_name = "INDEX/TOTAL", value_parser = ctest::parse_shard, requires = "test_step")]
+    test_shard: Option<(usize, usize)>,
+
+    /// JUnit report from an earlier run used to balance shards by duration
+    #[clap(long, value_name = "REPORT", requires = "test_shard")]
+    test_timings: Option<String>,
+
+    /// Run the tests under a wrapper such as `valgrind --leak-check=full`.
+    /// This is CTest's memcheck mode with MemoryCheckCommand overwritten, so
+    /// the project must include(CTest) and the wrapper must be a memory
+    /// checker CTest recognises (Valgrind, Dr. Memory, ...). The wrapper logs
+    /// are copied to BUILD_DIR/Logs/test-wrapper.
+    #[clap(long, value_name = "CMD", requires = "test_step")]
+    test_wrapper: Option<String>,
+
+    /// Run the tests with `ctest -T memcheck` using the memory checker the
+    /// project configured, copying its logs to BUILD_DIR/Logs/memcheck
+    #[clap(long, action)]
+    memcheck: bool,
+
+    /// Fail the memcheck step when it finds more defects than this
+    #[clap(long, value_name = "N", default_value_t = 0, requires = "memcheck")]
+    memcheck_max_defects: u64,
+
+    /// Build with a sanitizer, may be repeated. This sets CMAKE_C_FLAGS,
+    /// CMAKE_CXX_FLAGS and the linker flags, replacing what the cache had.
+    #[clap(long, value_enum, value_name = "SANITIZER")]
+    sanitize: Vec<Sanitizer>,
+
+    /// Configure with the compilers, sanitizers and cache variables of a
+    /// [toolchain.NAME] profile from cli_assist.toml. Its environment is set
+    /// for the configure, build, test and install commands.
+    #[clap(long, value_name = "NAME")]
+    toolchain_profile: Option<String>,
+
+    /// Print the toolchain profiles cli_assist.toml defines, then exit
+    #[clap(long, action)]
+    list_variants: bool,
+
+    /// Toolchain resolved from `toolchain_profile`
+    #[clap(skip)]
+    toolchain: Option<config::Toolchain>,
+
+    /// Suppressions file for the sanitizers, passed to the tests through
+    /// LSAN_OPTIONS, UBSAN_OPTIONS or TSAN_OPTIONS
+    #[clap(long, value_name = "FILE", requires = "sanitize", value_parser = existing_file)]
+    sanitize_suppressions: Option<String>,
+
+    /// Build the libFuzzer target TARGET and fuzz it. Everything is compiled
+    /// with -fsanitize=fuzzer-no-link,address, TARGET itself must link with
+    /// -fsanitize=fuzzer. Needs clang, GCC has no libFuzzer.
+    #[clap(long, value_name = "TARGET")]
+    fuzz: Option<String>,
+
+    /// How long to fuzz for
+    #[clap(long, value_name = "SECONDS", default_value_t = 60, requires = "fuzz")]
+    fuzz_time: u64,
+
+    /// Corpus directory the fuzzer reads and extends [default: BUILD_DIR/fuzz-corpus/TARGET]
+    #[clap(long, value_name = "DIR", requires = "fuzz")]
+    fuzz_corpus: Option<String>,
+
+    /// Set the KEY=VALUE lines of this file in the tests' environment. Blank
+    /// lines and lines starting with # are skipped.
+    #[clap(long, value_name = "PATH", requires = "test_step")]
+    test_env_file: Option<String>,
+
+    /// After the tests, list the N slowest from the JUnit report
+    #[clap(long, value_name = "N", requires = "test_step")]
+    slowest_tests: Option<usize>,
+
+    /// Write build metrics here as JSON: wall time overall and per step, files
+    /// compiled (Ninja only) and peak child memory use (Unix only)
+    #[clap(long, value_name = "PATH")]
+    metrics_json: Option<String>,
+
+    /// Write a JSON summary of the steps run and the test results here
+    #[clap(long, value_name = "PATH")]
+    summary_json: Option<String>,
+
+    /// Directory to write test reports to [default: BUILD_DIR]. When the tests
+    /// and coverage both run it also gets coverage.xml and a summary.json
+    /// linking the two.
+    #[clap(long)]
+    report_dir: Option<String>,
+
     /// Execute Code Coverage on the CMake Project
     #[clap(long, action)]
     coverage: bool,
@@ -36,6 +404,56 @@ pub struct CmakeVars {
     #[clap(short, long, action)]
     install: bool,
 
+    /// RPATH for installed binaries, e.g. `$ORIGIN/../lib` for a relocatable
+    /// install. CMake rewrites it at install time, so stripped installs
+    /// (install/strip) keep it too.
+    #[clap(long, value_name = "PATH")]
+    install_rpath: Option<String>,
+
+    /// Also add the directories linked against at build time to the install RPATH
+    #[clap(long, action, requires = "install_rpath")]
+    install_rpath_link_path: bool,
+
+    /// Install through `cmake --install --parallel`, requires CMake 3.31+ and
+    /// the project's INSTALL_PARALLEL property. Older CMake falls back to the
+    /// install target.
+    #[clap(long, action)]
+    install_parallel: bool,
+
+    /// Install into PREFIX instead of CMAKE_INSTALL_PREFIX, through
+    /// `cmake --install --prefix`. May be repeated or comma separated to
+    /// install into each, every install is its own step in the summary.
+    #[clap(long, value_name = "PREFIX", value_delimiter = ',')]
+    install_prefix: Vec<String>,
+
+    /// Command to run against the install prefix after installing, e.g.
+    /// `myapp --version`. The prefix's bin and lib directories are put on
+    /// PATH and LD_LIBRARY_PATH.
+    #[clap(long, value_name = "CMD")]
+    smoke_test: Option<String>,
+
+    /// Install, then check the install put a `<Package>Config.cmake` or
+    /// `<package>-config.cmake` into the prefix for find_package
+    #[clap(long, action)]
+    install_export: bool,
+
+    /// After installing, move the debug info of installed ELF binaries into
+    /// `.debug` files next to them, using CMAKE_OBJCOPY
+    #[clap(long, action)]
+    split_debug: bool,
+
+    /// Archive the build directory into this .tar.gz once all steps have run
+    #[clap(long, value_name = "PATH.tar.gz")]
+    archive: Option<String>,
+
+    /// Only archive build directory files matching this glob
+    #[clap(long, value_name = "GLOB", requires = "archive")]
+    archive_glob: Option<String>,
+
+    /// Keep object files in the archive, they're left out by default
+    #[clap(long, action, requires = "archive")]
+    archive_full: bool,
+
     /// Build a specfic CMake target
     #[clap(short, long)]
     target: Option<String>,
@@ -44,18 +462,244 @@ pub struct CmakeVars {
     #[clap(long, action)]
     tidy: bool,
 
+    /// Build serially to surface missing dependency edges, intentionally slow
+    /// and only meant for diagnosing the build
+    #[clap(long, action)]
+    no_parallel: bool,
+
+    /// Build and install with N jobs, or auto to size them to the CPUs this
+    /// process may use: its CPU affinity capped by a cgroup v1 or v2 CPU
+    /// quota on Linux, so containers don't over-subscribe. Without a quota,
+    /// or on other platforms, auto uses every CPU the OS reports.
+    /// [default: the build tool's own default]
+    #[clap(long, value_name = "N|auto", value_parser = jobs::parse, conflicts_with = "no_parallel")]
+    jobs: Option<jobs::Jobs>,
+
+    /// Skip the build step when no C/C++ source or header, CMakeLists.txt,
+    /// .cmake file or CMake preset changed in the git work tree, and the
+    /// configure left CMakeCache.txt as it was, since the last successful
+    /// build with this flag. The stamp is kept in BUILD_DIR.
+    #[clap(long, action)]
+    build_only_if_sources_changed: bool,
+
+    /// Target the build step builds [default: ALL_BUILD for Visual Studio and
+    /// Xcode, all for the other generators]
+    #[clap(long, value_name = "NAME")]
+    all_target: Option<String>,
+
+    /// Compile through a compiler cache, set up at configure time through
+    /// CMAKE_C_COMPILER_LAUNCHER and CMAKE_CXX_COMPILER_LAUNCHER
+    #[clap(long, value_enum, value_name = "TOOL")]
+    compiler_cache: Option<CompilerCache>,
+
+    /// Cache directory shared by every build directory, e.g. a Debug and a
+    /// Release tree, passed to the build as CCACHE_DIR or SCCACHE_DIR
+    /// [default: the cache tool's own]
+    #[clap(long, value_name = "DIR")]
+    compiler_cache_dir: Option<String>,
+
+    /// Print the compiler cache's hits, misses and hit rate for the build
+    /// step. Reads ccache unless --compiler-cache picks sccache.
+    #[clap(long, action)]
+    ccache_stats: bool,
+
+    /// Limit concurrent link steps via a Ninja job pool, set up at configure
+    /// time through CMAKE_JOB_POOLS and CMAKE_JOB_POOL_LINK (CMake 3.11+)
+    #[clap(long, value_name = "N")]
+    link_jobs: Option<u32>,
+
+    /// Don't start new build jobs above this load average (Make and Ninja only)
+    #[clap(long)]
+    max_load: Option<f64>,
+
+    /// Print the compile flags of a target or source prefix from compile_commands.json
+    #[clap(long, value_name = "TARGET|PREFIX")]
+    compile_flags: Option<String>,
+
+    /// Preload the cache from this CMake script, passed as `-C FILE`. It runs
+    /// before the other -D options are applied, so those override it.
+    #[clap(long, value_name = "FILE", value_parser = existing_file)]
+    initial_cache: Option<String>,
+
+    /// Prefix to search for packages in, may be repeated
+    #[clap(long, value_name = "PATH")]
+    prefix_path: Vec<String>,
+
+    /// Directory to search for CMake modules in, may be repeated
+    #[clap(long, value_name = "PATH")]
+    module_path: Vec<String>,
+
+    /// Pass a numeric version derived from git to configure as
+    /// PROJECT_VERSION, and `repo version` as PROJECT_VERSION_DESCRIBE
+    #[clap(long, action)]
+    version_from_git: bool,
+
+    /// clang-tidy binary to run, e.g. a versioned clang-tidy-17
+    #[clap(long, env = "CLANG_TIDY_BIN", default_value = "clang-tidy")]
+    clang_tidy_bin: String,
+
+    /// Apply the fixes exported by an earlier --tidy run with
+    /// clang-apply-replacements, without analysing again
+    #[clap(long, action)]
+    tidy_apply_fixes: bool,
+
+    /// clang-apply-replacements binary used by --tidy-apply-fixes
+    #[clap(long, env = "CLANG_APPLY_REPLACEMENTS_BIN", default_value = "clang-apply-replacements")]
+    clang_apply_replacements_bin: String,
+
+    /// Only run tidy on files matching this regex, may be repeated. A file
+    /// must match one include and no exclude, so excludes win over includes.
+    #[clap(long, value_name = "PATTERN")]
+    tidy_include: Vec<String>,
+
+    /// Don't exclude the build directory, _deps and third_party from tidy
+    #[clap(long, action)]
+    no_default_excludes: bool,
+
+    /// Remove the ClangTidy output directory before running, so the logs and
+    /// fixes only reflect this run
+    #[clap(long, action, requires = "tidy")]
+    tidy_clean: bool,
+
+    /// Write every clang-tidy diagnostic here as a JSON array of objects with
+    /// file, line, column, level, message and flag (the check name)
+    #[clap(long, value_name = "PATH", requires = "tidy")]
+    tidy_json: Option<String>,
+
+    /// Only fail tidy on diagnostics this baseline doesn't list. Diagnostics
+    /// match on file, check and message, line numbers are ignored.
+    #[clap(long, value_name = "FILE", requires = "tidy")]
+    tidy_baseline: Option<String>,
+
+    /// Write this run's diagnostics to --tidy-baseline as the accepted set
+    #[clap(long, action, requires = "tidy_baseline")]
+    tidy_write_baseline: bool,
+
+    /// Analyse at most N files with clang-tidy, the first N in path order
+    #[clap(long, value_name = "N")]
+    tidy_limit: Option<usize>,
+
+    /// Pick the --tidy-limit files at random instead of the first N
+    #[clap(long, action, requires = "tidy_limit")]
+    tidy_sample: bool,
+
+    /// Reuse clang-tidy results for unchanged files from this directory
+    #[clap(long, value_name = "DIR")]
+    tidy_cache_dir: Option<String>,
+
+    /// Configure and build through this configure preset and its build preset.
+    /// The preset owns the build directory, so BUILD_DIR should match its
+    /// binaryDir for the steps that read it.
+    #[clap(long)]
+    preset: Option<String>,
+
+    /// Configuration to build with a multi-config generator, e.g. Release
+    #[clap(long)]
+    config: Option<String>,
+
+    /// Build preset resolved from `preset`
+    #[clap(skip)]
+    build_preset: Option<String>,
+
+    /// Write the target dependency graph to this .dot file while configuring
+    #[clap(long, value_name = "PATH")]
+    graphviz: Option<String>,
+
+    /// Also render the graph with Graphviz's `dot`, skipped if it isn't installed
+    #[clap(long, value_name = "FORMAT", value_parser = ["png", "svg"], requires = "graphviz")]
+    graphviz_render: Option<String>,
+
+    /// Color compiler diagnostics through CMAKE_COLOR_DIAGNOSTICS (CMake 3.24+)
+    #[clap(long, value_enum, default_value_t = ColorMode::Auto)]
+    compiler_color: ColorMode,
+
+    /// Set up for editors and clangd: configure with a compile database, link
+    /// it into the repo root and stop. Nothing is built.
+    #[clap(long, action)]
+    ide_setup: bool,
+
+    /// Regenerate compile_commands.json by re-running CMake on the existing
+    /// cache with CMAKE_EXPORT_COMPILE_COMMANDS=ON, relink it into the repo root
+    /// and stop. Only the Makefile and Ninja generators write a compile database.
+    #[clap(long, action, conflicts_with = "ide_setup")]
+    refresh_compile_db: bool,
+
     /// Configure CMake for the Release Configuration
     #[clap(long, action)]
     release: bool,
 }
 
 pub fn process(cmds: CmakeVars) {
-    let mut status = true;
+    if !cmds.watch {
+        return run_steps(cmds);
+    }
+
+    let build_path = env::var("BUILD_DIR").expect("BUILD_DIR environment variable not set");
+    let repo_root = env::var("REPO_ROOT").unwrap_or_else(|_| ".".to_string());
+
+    let mut last = watch::snapshot(&repo_root, &build_path);
+    run_steps(cmds.clone());
+    loop {
+        println!("Watching {repo_root} for changes");
+        let changed = watch::wait_for_change(&repo_root, &build_path, &mut last);
+        watch::print_header(&changed, cmds.watch_clear);
+        run_steps(cmds.clone());
+    }
+}
+
+fn run_steps(mut cmds: CmakeVars) {
+    let start = Instant::now();
+    let mut steps = Steps::new();
     let build_path = env::var("BUILD_DIR")
         .expect("BUILD_DIR environment variable not set");
 
-    if cmds.destroy && Path::new(&build_path).exists() {
-        status = destroy_cmake(&build_path)
+    if cmds.list_variants {
+        let status = list_variants();
+        println!("CMake finished with: {status}");
+        return;
+    }
+
+    match config::load() {
+        Ok(config) => {
+            if let Some(name) = cmds.toolchain_profile.clone() {
+                match config.toolchain(&name).and_then(|toolchain| apply_toolchain(&mut cmds, toolchain)) {
+                    Ok(()) => println!("Using toolchain profile {name}"),
+                    Err(e) => {
+                        eprintln!("{e}");
+                        steps.status = false;
+                    }
+                }
+            }
+            steps.retries = config.retries;
+        }
+        Err(e) => {
+            eprintln!("{e}");
+            steps.status = false;
+        }
+    }
+    if let Some(ref dir) = cmds.compiler_cache_dir {
+        // Compilers run from all over the build tree, a relative cache
+        // directory would be a different one for each.
+        match path::absolute(dir) {
+            Ok(dir) => cmds.compiler_cache_dir = Some(dir.to_string_lossy().into_owned()),
+            Err(e) => {
+                eprintln!("Failed to resolve --compiler-cache-dir {dir}: {e}");
+                steps.status = false;
+            }
+        }
+    }
+    for (step, count) in &cmds.retry {
+        steps.retries.entry(step.clone()).or_default().count = *count;
+    }
+
+    if let Some(ref preset) = cmds.preset {
+        match presets::build_preset(".", preset, cmds.config.as_deref()) {
+            Ok(build_preset) => cmds.build_preset = Some(build_preset),
+            Err(e) => {
+                eprintln!("{e}");
+                steps.status = false;
+            }
+        }
     }
 
     let (cmake_target, target) = match cmds.target {
@@ -66,140 +710,1304 @@ pub fn process(cmds: CmakeVars) {
     // Must check in order of impact. I.e. If coverage is enabled, it needs to
     // enable tests.
     let release = cmds.release;
-    let install = cmds.install;
+    let clean = cmds.clean;
+    let install = cmds.install
+        || cmds.smoke_test.is_some()
+        || cmds.split_debug
+        || cmds.install_export
+        || !cmds.install_prefix.is_empty();
     let coverage = cmds.coverage;
     let tidy = cmds.tidy;
-    let test = cmds.test || coverage;
-    let build = cmds.build || test || install || tidy;
-    let configure = cmds.configure || build || release || target || tidy;
+    let test = cmds.test || coverage || cmds.ci;
+    // Memcheck and submit run the tests too, so they must be configured.
+    let configure_tests = test || cmds.memcheck || cmds.submit;
+    let build = cmds.build || test || install || tidy || cmds.memcheck || cmds.submit;
+    let preset = cmds.preset.is_some();
+    let graphviz = cmds.graphviz.is_some();
+    let fuzz = cmds.fuzz.is_some();
+    let configure = !cmds.assume_configured
+        && (cmds.configure
+            || build
+            || release
+            || target
+            || tidy
+            || clean
+            || preset
+            || graphviz
+            || cmds.feature_summary
+            || fuzz
+            || cmds.toolchain_profile.is_some());
 
-    if target && cmake_target == "clean" && Path::new(&build_path).exists() {
-        // If this doesn't run as a true clean, it will just run a configure,
-        // esentially acting as a call to configure a fresh BUILD_DIR.
-        status = clean_cmake(&build_path);
+    if cmds.list_steps {
+        let mut table: Vec<(String, Vec<(bool, &str)>)> = vec![
+            ("check-build-dir".into(), vec![(cmds.require_local_build_dir, "--require-local-build-dir")]),
+            ("destroy".into(), vec![(cmds.destroy, "--destroy")]),
+            ("clean".into(), vec![(clean, "--clean")]),
+            ("check-cache".into(), vec![
+                (cmds.assume_configured, "--assume-configured"),
+                (cmds.refresh_compile_db, "--refresh-compile-db"),
+            ]),
+            ("fuzz-check".into(), vec![(fuzz, "--fuzz")]),
+            ("configure".into(), vec![
+                (cmds.configure, "--configure"),
+                (build, "build step"),
+                (release, "--release"),
+                (target, "--target"),
+                (clean, "--clean"),
+                (preset, "--preset"),
+                (graphviz, "--graphviz"),
+                (cmds.feature_summary, "--feature-summary"),
+                (fuzz, "--fuzz"),
+                (cmds.toolchain_profile.is_some(), "--toolchain-profile"),
+            ].into_iter()
+                .map(|(enabled, reason)| (configure && enabled, reason))
+                .chain([(cmds.ide_setup, "--ide-setup")])
+                .collect()),
+            ("refresh-compile-db".into(), vec![(cmds.refresh_compile_db, "--refresh-compile-db")]),
+            ("ide-setup".into(), vec![
+                (cmds.ide_setup, "--ide-setup"),
+                (cmds.refresh_compile_db, "--refresh-compile-db"),
+            ]),
+            ("compile-flags".into(), vec![(cmds.compile_flags.is_some(), "--compile-flags")]),
+            ("build".into(), vec![
+                (cmds.build, "--build"),
+                (test, "test step"),
+                (install, "install step"),
+                (tidy, "--tidy"),
+                (cmds.memcheck, "--memcheck"),
+                (cmds.submit, "--submit"),
+            ]),
+            ("target".into(), vec![(target, "--target")]),
+            ("test".into(), vec![(cmds.test, "--test"), (coverage, "--coverage"), (cmds.ci, "--ci")]),
+            ("memcheck".into(), vec![(cmds.memcheck, "--memcheck")]),
+            ("submit".into(), vec![(cmds.submit, "--submit")]),
+            ("coverage".into(), vec![(coverage, "--coverage")]),
+            ("tidy".into(), vec![(tidy, "--tidy")]),
+            ("tidy-apply".into(), vec![(cmds.tidy_apply_fixes, "--tidy-apply-fixes")]),
+            ("fuzz-build".into(), vec![(fuzz, "--fuzz")]),
+            ("fuzz".into(), vec![(fuzz, "--fuzz")]),
+        ];
+        for prefix in install_prefixes(&cmds) {
+            let name = |step: &str| match prefix {
+                Some(prefix) => format!("{step}:{prefix}"),
+                None => step.to_string(),
+            };
+            table.extend([
+                (name("install"), vec![
+                    (cmds.install, "--install"),
+                    (cmds.smoke_test.is_some(), "--smoke-test"),
+                    (cmds.split_debug, "--split-debug"),
+                    (cmds.install_export, "--install-export"),
+                    (!cmds.install_prefix.is_empty(), "--install-prefix"),
+                ]),
+                (name("install-export"), vec![(cmds.install_export, "--install-export")]),
+                (name("split-debug"), vec![(cmds.split_debug, "--split-debug")]),
+                (name("smoke-test"), vec![(cmds.smoke_test.is_some(), "--smoke-test")]),
+            ]);
+        }
+        table.push(("archive".into(), vec![(cmds.archive.is_some(), "--archive")]));
+        if cmds.ide_setup || cmds.refresh_compile_db {
+            // Both stop after their own steps, whatever else was asked for.
+            for trigger in table.iter_mut().flat_map(|(_, triggers)| triggers) {
+                trigger.0 &= ["--ide-setup", "--refresh-compile-db"].contains(&trigger.1);
+            }
+        }
+        print_steps(&table);
+        return;
     }
 
-    if status && configure {
-        status = configure_cmake(cmds, release, &build_path)
+    if let Some(ref script_path) = cmds.emit_script {
+        let mut script = script::Script::new();
+        let quoted_build = script::quote(&build_path);
+        if cmds.require_local_build_dir {
+            script.skipped("check-build-dir");
+        }
+        if cmds.destroy {
+            script.line("destroy", &format!("rm -rf {quoted_build}"));
+        }
+        if clean {
+            let clean_cmd = script::render(&target_command(&cmds, "clean", &build_path));
+            script.line("clean", &format!("if [ -d {quoted_build} ]; then {clean_cmd}; fi"));
+        }
+        if cmds.assume_configured {
+            script.line("check-cache", &format!("test -f {quoted_build}/CMakeCache.txt"));
+        }
+        if configure {
+            match configure_command(&cmds, release, configure_tests, &build_path) {
+                Ok(configure_cmd) => script.command("configure", &configure_cmd),
+                Err(e) => {
+                    eprintln!("{e}");
+                    steps.status = false;
+                }
+            }
+        }
+        if cmds.compile_flags.is_some() {
+            script.skipped("compile-flags");
+        }
+        if build {
+            script.command("build", &target_command(&cmds, &build_target(&cmds, &build_path), &build_path));
+        }
+        if target {
+            script.command("target", &target_command(&cmds, &cmake_target, &build_path));
+        }
+        if test && cmds.test_shard.is_some() {
+            // Shards are only known once the tests can be listed.
+            script.skipped("test");
+        } else if let Some(ref ctest_script) = cmds.ctest_script {
+            script.line("test", &format!("CTEST_OUTPUT_ON_FAILURE=1 ctest -S {}", script::quote(ctest_script)));
+        } else if test {
+            match test_command(&cmds, &build_path, &junit_report(&cmds, &build_path), None) {
+                Ok(test_cmd) => script.command("test", &test_cmd),
+                Err(e) => {
+                    eprintln!("{e}");
+                    steps.status = false;
+                }
+            }
+        }
+        for (name, enabled) in [("memcheck", cmds.memcheck), ("submit", cmds.submit)] {
+            if enabled {
+                script.skipped(name);
+            }
+        }
+        if coverage {
+            script.command("coverage", &target_command(&cmds, "ExperimentalCoverage", &build_path));
+        }
+        for (name, enabled) in [("tidy", tidy), ("tidy-apply", cmds.tidy_apply_fixes), ("fuzz", fuzz)] {
+            if enabled {
+                script.skipped(name);
+            }
+        }
+        if install {
+            let parallel = install_parallel(&cmds);
+            if cmds.install_prefix.is_empty() && !parallel {
+                script.command("install", &target_command(&cmds, "install", &build_path));
+            } else if cmds.install_prefix.is_empty() {
+                script.command("install", &install_command(&cmds, &build_path, None, parallel));
+            }
+            for prefix in &cmds.install_prefix {
+                script.command(&format!("install:{prefix}"), &install_command(&cmds, &build_path, Some(prefix), parallel));
+            }
+        }
+        for (name, enabled) in [
+            ("install-export", cmds.install_export),
+            ("split-debug", cmds.split_debug),
+            ("smoke-test", cmds.smoke_test.is_some()),
+        ] {
+            if enabled {
+                script.skipped(name);
+            }
+        }
+
+        if steps.status {
+            match script.write(script_path) {
+                Ok(()) => println!("Wrote the run's commands to {script_path}"),
+                Err(e) => {
+                    eprintln!("{e}");
+                    steps.status = false;
+                }
+            }
+        }
+        println!("CMake finished with: {}", steps.status);
+        return;
     }
 
-    if status && build {
-        status = build_cmake(&build_path)
+    if let Some(ref target) = cmds.dump_ninja_deps {
+        let status = dump_ninja_deps(&build_path, target);
+        println!("CMake finished with: {status}");
+        return;
     }
 
-    if status && target && cmake_target != "clean" {
-        status = target_cmake(&cmake_target, &build_path)
+    if cmds.depfile_check {
+        let status = depfile_check(&build_path);
+        println!("CMake finished with: {status}");
+        return;
     }
 
-    if status && test {
-        status = test_cmake(&build_path)
+    if let Some(ref name) = cmds.explain_var {
+        let status = explain_var(&build_path, name);
+        println!("CMake finished with: {status}");
+        return;
     }
 
-    if status && coverage {
-        status = coverage_cmake(&build_path)
+    if cmds.show_test_report {
+        show_test_report(&junit_report(&cmds, &build_path));
+        return;
     }
 
-    if status && tidy {
-        status = clang_tidy(&build_path)
+    if cmds.ide_setup {
+        steps.run("configure", true, || configure_cmake(&cmds, release, configure_tests, &build_path));
+        steps.run("ide-setup", true, || compile_db::link_to_repo_root(&build_path));
+        println!("CMake finished with: {}", steps.status);
+        return;
     }
 
-    if status && install {
-        status = install_cmake(&build_path)
+    if cmds.refresh_compile_db {
+        steps.run("check-cache", true, || check_cache(&build_path));
+        steps.run("refresh-compile-db", true, || refresh_compile_db(&build_path));
+        steps.run("ide-setup", true, || compile_db::link_to_repo_root(&build_path));
+        println!("CMake finished with: {}", steps.status);
+        return;
     }
 
-    println!("CMake finished with: {status}");
-}
+    steps.run("check-build-dir", cmds.require_local_build_dir, || check_local_build_dir(&build_path));
+    steps.run("destroy", cmds.destroy && Path::new(&build_path).exists(), || destroy_cmake(&cmds, &build_path));
+
+    // If this doesn't run as a true clean, it will just run a configure,
+    // esentially acting as a call to configure a fresh BUILD_DIR.
+    steps.run("clean", clean && Path::new(&build_path).exists(), || clean_cmake(&cmds, &build_path));
 
-fn destroy_cmake(artifacts: &String) -> bool {
-    match remove_dir_all(artifacts) {
-        Ok(_) => true,
-        _ => false,
+    steps.run("check-cache", cmds.assume_configured, || check_cache(&build_path));
+    steps.run("fuzz-check", fuzz, || {
+        let profile_cxx = cmds.toolchain.as_ref().and_then(|toolchain| toolchain.cxx_compiler.as_deref());
+        fuzz::check_compiler(&build_path, profile_cxx)
+    });
+    steps.run("configure", configure, || configure_cmake(&cmds, release, configure_tests, &build_path));
+
+    if let Some(ref filter) = cmds.compile_flags {
+        steps.run("compile-flags", true, || compile_db::print_flags(&build_path, filter));
     }
-}
 
-fn configure_cmake(cmds: CmakeVars, release: bool, artifacts: &String) -> bool {
-    let mut cmd = Command::new("cmake");
+    let log_before = ninja_log_entries(&build_path);
+    let fingerprint = match cmds.build_only_if_sources_changed {
+        true => build_stamp::fingerprint(&build_path),
+        false => None,
+    };
+    let up_to_date = build && fingerprint.is_some() && build_stamp::read(&build_path) == fingerprint;
+    if up_to_date {
+        println!("Build up to date, no relevant source or CMake cache changes since the last build");
+    }
 
-    let build_cfg = match release {
-        true => "-DCMAKE_BUILD_TYPE=Release",
-        false => "-DCMAKE_BUILD_TYPE=Debug",
+    let cache_before = match cmds.ccache_stats && build && !up_to_date {
+        true => compiler_cache_stats(&cmds),
+        false => None,
+    };
+    steps.run("build", build && !up_to_date, || build_cmake(&cmds, &build_path));
+    if cmds.ccache_stats && steps.ran("build") {
+        match (cache_before, compiler_cache_stats(&cmds)) {
+            (Some(before), Some(after)) => {
+                let stats = after.since(before);
+                println!(
+                    "Compiler cache: {} hits, {} misses, {:.1}% hit rate",
+                    stats.hits,
+                    stats.misses,
+                    stats.hit_rate()
+                );
+            }
+            _ => println!("Compiler cache: no statistics from {}", compiler_cache_tool(&cmds).program()),
+        }
+    }
+    if let (true, Some(fingerprint)) = (steps.ran("build") && steps.status, &fingerprint) {
+        build_stamp::write(&build_path, fingerprint);
+    }
+    let rebuilt = match steps.ran("build") {
+        true => rebuilt_outputs(log_before, ninja_log_entries(&build_path)),
+        false => None,
     };
+    steps.run("target", target, || target_cmake(&cmds, &cmake_target, &build_path));
+    steps.run("test", test, || test_cmake(&cmds, &build_path));
+    steps.run("memcheck", cmds.memcheck, || memcheck_cmake(&cmds, &build_path));
+    steps.run("submit", cmds.submit, || submit_cdash(&cmds, &build_path));
+    steps.run("coverage", coverage, || coverage_cmake(&cmds, &build_path));
+    steps.run("tidy", tidy, || clang_tidy(&cmds, &build_path));
+    steps.run("tidy-apply", cmds.tidy_apply_fixes, || tidy_apply_fixes(&cmds, &build_path));
+
+    if let Some(ref fuzz_target) = cmds.fuzz {
+        steps.run("fuzz-build", true, || target_cmake(&cmds, fuzz_target, &build_path));
+        steps.run("fuzz", true, || run_fuzzer(&cmds, fuzz_target, &build_path));
+    }
 
-    cmd.arg("-S").arg(".").arg("-B").arg(artifacts).arg("-G").arg("Ninja").arg(build_cfg);
+    // Each install rewrites install_manifest.txt, so the steps that read it
+    // or the installed files follow each prefix's install.
+    for prefix in install_prefixes(&cmds) {
+        let name = |step: &str| match prefix {
+            Some(prefix) => format!("{step}:{prefix}"),
+            None => step.to_string(),
+        };
+        steps.run(&name("install"), install, || install_cmake(&cmds, &build_path, prefix));
+        steps.run(&name("install-export"), cmds.install_export, || check_install_export(&build_path));
+        steps.run(&name("split-debug"), cmds.split_debug, || split_debug_info(&build_path));
 
-    if cmds.coverage {
-        cmd.arg("-Dtest=ON").arg("-DENABLE_COVERAGE=ON");
-    } else if cmds.test {
-        cmd.arg("-Dtest=ON");
+        if let Some(ref smoke_cmd) = cmds.smoke_test {
+            steps.run(&name("smoke-test"), true, || smoke_test(smoke_cmd, &build_path, prefix));
+        }
     }
 
-    cmd.status().expect("failed to execute process").success()
-}
+    if steps.ran("test") && steps.ran("coverage") {
+        let junit_file = junit_report(&cmds, &build_path);
+        if let Err(e) = summary::write_combined_report(&report_dir(&cmds, &build_path), &junit_file, &build_path) {
+            eprintln!("{e}");
+            steps.status = false;
+        }
+    }
 
-fn target_cmake(target: &str, artifacts: &String) -> bool {
-    Command::new("cmake")
-        .arg("--build")
-        .arg(artifacts)
-        .arg("--parallel")
-        .arg("--target")
-        .arg(target)
-        .status()
-        .expect("failed to execute process")
-        .success()
-}
+    if let Some(ref summary_path) = cmds.summary_json {
+        let tests = steps
+            .ran("test")
+            .then(|| summary::TestSummary::from_report(&junit_report(&cmds, &build_path)));
+        summary::write(summary_path, &steps, tests, rebuilt);
+    }
 
-fn build_cmake(artifacts: &String) -> bool {
-    target_cmake("all", artifacts)
-}
+    if cmds.feature_summary && steps.ran("configure") {
+        print_feature_summary(&build_path);
+    }
 
-fn coverage_cmake(artifacts: &String) -> bool {
-    target_cmake("ExperimentalCoverage", artifacts)
-}
+    if let Some(ref metrics_path) = cmds.metrics_json {
+        let compiled = rebuilt.and_then(|_| ninja_compiled_since(&build_path, log_before.unwrap_or_default()));
+        metrics::write(metrics_path, &steps, start.elapsed(), compiled);
+    }
+
+    // Archive regardless of status, a failed build is the one worth keeping.
+    if let Some(ref out_path) = cmds.archive {
+        if let Err(e) = archive::archive(&build_path, out_path, cmds.archive_glob.as_deref(), cmds.archive_full) {
+            eprintln!("{e}");
+            steps.status = false;
+        }
+    }
+
+    let status = steps.status;
+    println!("CMake finished with: {status}");
 
-fn install_cmake(artifacts: &String) -> bool {
-    target_cmake("install", artifacts)
+    if let Some(ref notify_cmd) = cmds.notify {
+        notify(notify_cmd, &steps, start.elapsed().as_secs());
+    }
+
+    if cmds.ci {
+        println!("CI verdict: {}", if status { "PASS" } else { "FAIL" });
+        if !status {
+            std::process::exit(1);
+        }
+    }
 }
 
-fn clean_cmake(artifacts: &String) -> bool {
-    target_cmake("clean", artifacts)
+/// The --install-prefix values, or None once for the cached prefix.
+fn install_prefixes(cmds: &CmakeVars) -> Vec<Option<&str>> {
+    match cmds.install_prefix.is_empty() {
+        true => vec![None],
+        false => cmds.install_prefix.iter().map(|prefix| Some(prefix.as_str())).collect(),
+    }
 }
 
-fn find_cpp_files(exlude_dirs: String, repo_root: String) -> Vec<String> {
-    // Search for .clang-tidy file
-    let mut glob_path = repo_root.to_owned();
-    glob_path.push_str("/**/*.cpp");
+/// Print each step that runs, in order, with the flags or steps that pulled it in.
+fn print_steps(steps: &[(String, Vec<(bool, &str)>)]) {
+    println!("Steps that will run, in order:");
 
-    let all_cpp_glob = glob(glob_path.as_str()).expect("Failed to read glob pattern");
-    let mut all_cpp_files = Vec::<String>::new();
-    let exlude_dirs = exlude_dirs.split(" ");
-    for file in all_cpp_glob {
-        let file = file.expect("Invalid file found in cpp glob").into_os_string().into_string().expect("Pathbuf into String");
-       
-        let mut regex_match = false;
-        for dir in exlude_dirs.clone() {
-            let regex = Regex::new(dir).unwrap();
-            if regex.is_match(file.as_str()) {
-                regex_match = true;
-            }
+    let mut num = 0;
+    for (name, triggers) in steps {
+        let reasons: Vec<&str> = triggers
+            .iter()
+            .filter(|(enabled, _)| *enabled)
+            .map(|(_, reason)| *reason)
+            .collect();
+
+        if !reasons.is_empty() {
+            num += 1;
+            println!("{num:>4}. {name:<18} from {}", reasons.join(", "));
         }
+    }
 
-        if regex_match {
-            all_cpp_files.push(file.clone());
+    if num == 0 {
+        println!("    none");
+    }
+
+    println!();
+    println!("Implications:");
+    // Steps with one trigger only run for their own flag, and a step that
+    // runs once per install prefix is explained once.
+    let mut explained = BTreeSet::new();
+    for (name, triggers) in steps.iter().filter(|(_, triggers)| triggers.len() > 1) {
+        let name = name.split_once(':').map_or(name.as_str(), |(step, _)| step);
+        if explained.insert(name) {
+            let reasons: Vec<&str> = triggers.iter().map(|(_, reason)| *reason).collect();
+            println!("    {name} runs for {}", reasons.join(", "));
         }
     }
+    println!("    --assume-configured replaces the configure with a check for CMakeCache.txt");
+    println!("    --ide-setup and --refresh-compile-db skip every other step");
+    println!("    a failing step stops the steps after it, --archive always runs");
+}
 
-    all_cpp_files
+fn check_local_build_dir(artifacts: &str) -> bool {
+    match local_fs::fs_kind(artifacts) {
+        Ok(local_fs::FsKind::Local) => true,
+        Ok(local_fs::FsKind::Network(fs_type)) => {
+            eprintln!("{artifacts} is on a network filesystem ({fs_type}), use a local build directory");
+            false
+        }
+        Ok(local_fs::FsKind::Fuse(fs_type)) => {
+            println!("Warning: {artifacts} is on {fs_type}, builds are slow if it is a network mount like sshfs");
+            true
+        }
+        Err(e) => {
+            println!("Warning: couldn't check the filesystem of {artifacts}: {e}");
+            true
+        }
+    }
 }
 
-fn clang_tidy(artifacts: &String) -> bool {
-    let repo_root = env::var("REPO_ROOT").expect("REPO_ROOT not set.");
+fn check_cache(artifacts: &str) -> bool {
+    let cache = combine_artifact_path(artifacts, "/CMakeCache.txt");
+    if !Path::new(&cache).exists() {
+        eprintln!("--assume-configured: {cache} not found, configure the build directory first");
+        return false;
+    }
 
-    // Search for .clang-tidy file
-    let cfg_loc = combine_artifact_path(&repo_root, "/**/.clang-tidy");
-    let cfg_loc = glob(cfg_loc.as_str())
-        .expect("Failed to find clang-tidy config")
-        .into_iter()
-        .next()
+    true
+}
+
+fn refresh_compile_db(artifacts: &str) -> bool {
+    let generator = read_cache_var(artifacts, "CMAKE_GENERATOR").unwrap_or_default();
+    if !generator.contains("Makefiles") && !generator.starts_with("Ninja") {
+        eprintln!("The '{generator}' generator doesn't write compile_commands.json");
+        return false;
+    }
+
+    // Re-running on the existing cache skips the compiler checks and cached
+    // find results, so this is much cheaper than a fresh configure.
+    Command::new("cmake")
+        .arg("-DCMAKE_EXPORT_COMPILE_COMMANDS=ON")
+        .arg(artifacts)
+        .status()
+        .expect("failed to execute process")
+        .success()
+}
+
+fn destroy_cmake(cmds: &CmakeVars, artifacts: &String) -> bool {
+    if !cmds.force {
+        if let Some(reason) = destroy::suspicion(artifacts, cmds.destroy_max_size.saturating_mul(1 << 30)) {
+            if !io::stdin().is_terminal() {
+                eprintln!("Not destroying {artifacts}, {reason}. Pass --force to destroy it anyway");
+                return false;
+            }
+
+            print!("Destroy {artifacts}? {reason} [y/N] ");
+            io::stdout().flush().unwrap();
+            let mut answer = String::new();
+            io::stdin().read_line(&mut answer).unwrap_or_default();
+            if !matches!(answer.trim(), "y" | "Y" | "yes") {
+                eprintln!("Not destroying {artifacts}");
+                return false;
+            }
+        }
+    }
+
+    remove_dir_all(artifacts).is_ok()
+}
+
+fn list_variants() -> bool {
+    let config = match config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{e}");
+            return false;
+        }
+    };
+
+    if config.toolchain.is_empty() {
+        println!("No toolchain profiles, add [toolchain.NAME] tables to {}", config::CONFIG_FILE);
+        return true;
+    }
+
+    let width = config.toolchain.keys().map(|name| name.len()).max().unwrap_or_default();
+    for (name, toolchain) in &config.toolchain {
+        let description = toolchain.description.as_deref().unwrap_or("(no description)");
+        println!("{name:<width$}  {description}");
+    }
+    true
+}
+
+/// Add a toolchain profile's sanitizers to --sanitize and keep the rest for
+/// the configure step.
+fn apply_toolchain(cmds: &mut CmakeVars, toolchain: config::Toolchain) -> Result<(), String> {
+    for name in &toolchain.sanitize {
+        let sanitizer = Sanitizer::from_str(name, true)
+            .map_err(|_| format!("Toolchain profile sanitizer '{name}' isn't one --sanitize takes"))?;
+        if !cmds.sanitize.contains(&sanitizer) {
+            cmds.sanitize.push(sanitizer);
+        }
+    }
+
+    cmds.toolchain = Some(toolchain);
+    Ok(())
+}
+
+fn configure_cmake(cmds: &CmakeVars, release: bool, test: bool, artifacts: &String) -> bool {
+    let success = run_configure(cmds, release, test, artifacts);
+
+    if let (true, Some(dot_file), Some(format)) = (success, &cmds.graphviz, &cmds.graphviz_render) {
+        render_graphviz(dot_file, format);
+    }
+
+    success
+}
+
+/// Render `dot_file` next to itself, the graph is optional so a missing
+/// `dot` only gets a note.
+fn render_graphviz(dot_file: &str, format: &str) {
+    let out_file = format!("{dot_file}.{format}");
+    let rendered = Command::new("dot")
+        .arg(format!("-T{format}"))
+        .arg(dot_file)
+        .arg("-o")
+        .arg(&out_file)
+        .status();
+
+    match rendered {
+        Ok(status) if status.success() => println!("Rendered dependency graph to {out_file}"),
+        Ok(_) => eprintln!("dot failed to render {dot_file}"),
+        Err(_) => println!("dot not found, skipping the graph render"),
+    }
+}
+
+fn run_configure(cmds: &CmakeVars, release: bool, test: bool, artifacts: &String) -> bool {
+    let mut cmd = match configure_command(cmds, release, test, artifacts) {
+        Ok(cmd) => cmd,
+        Err(e) => {
+            eprintln!("{e}");
+            return false;
+        }
+    };
+    let limit = cmds.configure_timeout.map(Duration::from_secs);
+    if !cmds.configure_werror && !cmds.feature_summary {
+        return run_step_within(cmds, &mut cmd, limit).unwrap_or_else(|| configure_timed_out(cmds));
+    }
+
+    let Some((success, output)) = run_captured_within(&mut cmd, limit) else {
+        return configure_timed_out(cmds);
+    };
+    if !success && cmds.explain_failure {
+        explain::explain(&output);
+    }
+
+    if cmds.feature_summary {
+        make_and_write_file(configure_log(artifacts), output.as_bytes());
+    }
+
+    if !cmds.configure_werror {
+        return success;
+    }
+
+    let warnings = configure_warnings(&output);
+    if !warnings.is_empty() {
+        eprintln!("--configure-werror: {} configure warnings", warnings.len());
+        for warning in &warnings {
+            eprintln!("    {warning}");
+        }
+    }
+
+    success && warnings.is_empty()
+}
+
+/// The configure command, with the project's tests enabled when `test` is
+/// set, i.e. when any step will run them.
+fn configure_command(cmds: &CmakeVars, release: bool, test: bool, artifacts: &String) -> Result<Command, String> {
+    let mut cmd = Command::new("cmake");
+
+    let build_cfg = match release {
+        true => "-DCMAKE_BUILD_TYPE=Release",
+        false => "-DCMAKE_BUILD_TYPE=Debug",
+    };
+
+    // CMake applies -C and -D in order, so the script comes first.
+    if let Some(ref initial_cache) = cmds.initial_cache {
+        cmd.arg("-C").arg(initial_cache);
+    }
+
+    match cmds.preset {
+        // The preset picks the generator and build directory.
+        Some(ref preset) => cmd.arg("-S").arg(".").arg("--preset").arg(preset),
+        None => cmd.arg("-S").arg(".").arg("-B").arg(artifacts).arg("-G").arg("Ninja").arg(build_cfg),
+    };
+
+    if cmds.coverage {
+        cmd.arg("-Dtest=ON").arg("-DENABLE_COVERAGE=ON");
+    } else if test {
+        cmd.arg("-Dtest=ON");
+    }
+
+    // Captured steps pipe compiler output, so compilers can't see the
+    // terminal themselves and need telling.
+    let color = match cmds.compiler_color {
+        ColorMode::Auto => io::stdout().is_terminal().then_some("ON"),
+        ColorMode::Always => Some("ON"),
+        ColorMode::Never => Some("OFF"),
+    };
+    if let Some(color) = color {
+        cmd.arg(format!("-DCMAKE_COLOR_DIAGNOSTICS={color}"));
+    }
+
+    if cmds.ide_setup {
+        cmd.arg("-DCMAKE_EXPORT_COMPILE_COMMANDS=ON");
+    }
+
+    if let Some(ref dot_file) = cmds.graphviz {
+        cmd.arg(format!("--graphviz={dot_file}"));
+    }
+
+    if let Some(ref rpath) = cmds.install_rpath {
+        cmd.arg(format!("-DCMAKE_INSTALL_RPATH={rpath}"));
+        if cmds.install_rpath_link_path {
+            cmd.arg("-DCMAKE_INSTALL_RPATH_USE_LINK_PATH=ON");
+        }
+    }
+
+    let mut names: Vec<&str> = cmds.sanitize.iter().map(|sanitizer| sanitizer.flag()).collect();
+    if cmds.fuzz.is_some() {
+        // Instrument everything for coverage feedback, only the fuzz target
+        // links the fuzzer's main().
+        names.push("fuzzer-no-link");
+        if !names.contains(&"address") {
+            names.push("address");
+        }
+    }
+    if !names.is_empty() {
+        let flags = format!("-fsanitize={} -fno-omit-frame-pointer", names.join(","));
+        for var in ["CMAKE_C_FLAGS", "CMAKE_CXX_FLAGS", "CMAKE_EXE_LINKER_FLAGS", "CMAKE_SHARED_LINKER_FLAGS"] {
+            cmd.arg(format!("-D{var}={flags}"));
+        }
+    }
+
+    if let Some(tool) = cmds.compiler_cache {
+        for var in ["CMAKE_C_COMPILER_LAUNCHER", "CMAKE_CXX_COMPILER_LAUNCHER"] {
+            cmd.arg(format!("-D{var}={}", tool.program()));
+        }
+    }
+
+    if let Some(jobs) = cmds.link_jobs {
+        // Job pools only exist for the Ninja generators, others ignore them.
+        cmd.arg(format!("-DCMAKE_JOB_POOLS=link_pool={jobs}"))
+            .arg("-DCMAKE_JOB_POOL_LINK=link_pool");
+    }
+
+    if cmds.version_from_git {
+        // project(VERSION) only takes N[.N[.N[.N]]], so the full describe
+        // string goes in its own variable.
+        let version = crate::repo::git_numeric_version()?;
+        let describe = crate::repo::git_version().ok_or("Failed to derive a version, is this a git repository?")?;
+        cmd.arg(format!("-DPROJECT_VERSION={version}"))
+            .arg(format!("-DPROJECT_VERSION_DESCRIBE={describe}"));
+    }
+
+    if !cmds.prefix_path.is_empty() {
+        // The environment form of CMAKE_PREFIX_PATH is a native path list, so
+        // it composes with whatever the caller already exported.
+        prepend_env_paths(&mut cmd, "CMAKE_PREFIX_PATH", &cmds.prefix_path)?;
+    }
+
+    if !cmds.module_path.is_empty() {
+        // CMake cache lists are always `;` separated, whatever the platform.
+        cmd.arg(format!("-DCMAKE_MODULE_PATH={}", cmds.module_path.join(";")));
+    }
+
+    if let Some(ref toolchain) = cmds.toolchain {
+        cmd.args(toolchain.cmake_args());
+    }
+    set_profile_env(cmds, &mut cmd);
+
+    if cmds.configure_werror {
+        cmd.arg("-Wdev").arg("-Wdeprecated");
+    }
+
+    Ok(cmd)
+}
+
+fn configure_timed_out(cmds: &CmakeVars) -> bool {
+    let secs = cmds.configure_timeout.unwrap_or_default();
+    eprintln!("configure-timeout: configure ran over {secs}s and was killed, a FetchContent download may be stuck");
+    false
+}
+
+/// The header lines of each warning CMake printed while configuring.
+fn configure_warnings(output: &str) -> Vec<&str> {
+    let regex = Regex::new(r"^CMake (Warning|Deprecation Warning)").unwrap();
+    output.lines().filter(|line| regex.is_match(line)).collect()
+}
+
+fn configure_log(artifacts: &str) -> String {
+    combine_artifact_path(&log_dir(artifacts), "/configure.log")
+}
+
+/// The block feature_summary() printed while configuring: its
+/// `-- The following ...` headings and the ` * ` entries under them.
+fn feature_summary(output: &str) -> Option<String> {
+    let mut block = String::new();
+    let mut in_block = false;
+    for line in output.lines() {
+        if line.starts_with("-- The following ") {
+            in_block = true;
+        } else if line.starts_with("--") || line.starts_with("CMake ") {
+            in_block = false;
+        }
+
+        if in_block {
+            block.push_str(line);
+            block.push('\n');
+        }
+    }
+
+    (!block.is_empty()).then_some(block)
+}
+
+fn print_feature_summary(artifacts: &str) {
+    let output = fs::read_to_string(configure_log(artifacts)).unwrap_or_default();
+    match feature_summary(&output) {
+        Some(block) => println!("Feature summary:\n{}", block.trim_end()),
+        None => println!("No feature summary found, the project doesn't call feature_summary()"),
+    }
+}
+
+fn existing_file(text: &str) -> Result<String, String> {
+    match Path::new(text).is_file() {
+        true => Ok(text.to_string()),
+        false => Err(format!("{text} is not a file")),
+    }
+}
+
+/// Separator of native path lists such as PATH and CMAKE_PREFIX_PATH.
+const PATH_LIST_SEPARATOR: char = if cfg!(windows) { ';' } else { ':' };
+
+/// Join `paths` into a path list, failing if a path contains `separator`.
+fn join_path_list(paths: &[String], separator: char) -> Result<String, String> {
+    if let Some(path) = paths.iter().find(|path| path.contains(separator)) {
+        return Err(format!("'{path}' contains the path list separator '{separator}'"));
+    }
+
+    Ok(paths.join(&separator.to_string()))
+}
+
+fn target_cmake(cmds: &CmakeVars, target: &str, artifacts: &String) -> bool {
+    let mut cmd = target_command(cmds, target, artifacts);
+    if !cmds.capture_build {
+        return run_step(cmds, &mut cmd);
+    }
+
+    let (success, output) = run_captured(&mut cmd);
+    if !success && cmds.explain_failure {
+        explain::explain(&output);
+    }
+
+    let log_file = combine_artifact_path(&log_dir(artifacts), &format!("/build-{target}.log"));
+    make_and_write_file(log_file, output.as_bytes());
+
+    if cmds.warnings_summary {
+        print_warnings_summary(&output);
+    }
+
+    success
+}
+
+fn target_command(cmds: &CmakeVars, target: &str, artifacts: &String) -> Command {
+    let mut cmd = Command::new("cmake");
+    match cmds.build_preset {
+        Some(ref build_preset) => cmd.arg("--build").arg("--preset").arg(build_preset),
+        None => cmd.arg("--build").arg(artifacts),
+    };
+
+    if let Some(ref config) = cmds.config {
+        cmd.arg("--config").arg(config);
+    }
+
+    if cmds.no_parallel {
+        // Ninja runs in parallel unless told otherwise, so pin it to one job.
+        cmd.arg("--parallel").arg("1");
+    } else if let Some(count) = job_count(cmds) {
+        cmd.arg("--parallel").arg(count.to_string());
+    } else {
+        cmd.arg("--parallel");
+    }
+
+    cmd.arg("--target").arg(target);
+    set_profile_env(cmds, &mut cmd);
+
+    if let Some(ref dir) = cmds.compiler_cache_dir {
+        cmd.env(compiler_cache_tool(cmds).dir_var(), dir);
+    }
+
+    let native_args = native_build_args(cmds, artifacts);
+    if !native_args.is_empty() {
+        cmd.arg("--").args(native_args);
+    }
+
+    cmd
+}
+
+/// Run a step's command, capturing its output when --explain-failure needs it.
+fn run_step(cmds: &CmakeVars, cmd: &mut Command) -> bool {
+    run_step_within(cmds, cmd, None).unwrap_or_default()
+}
+
+/// Like `run_step`, but kill the command once it runs longer than `limit`.
+/// None when it was killed.
+fn run_step_within(cmds: &CmakeVars, cmd: &mut Command, limit: Option<Duration>) -> Option<bool> {
+    if !cmds.explain_failure {
+        let Some(limit) = limit else {
+            return Some(cmd.status().expect("failed to execute process").success());
+        };
+
+        timeout::isolate(cmd);
+        let mut child = cmd.spawn().expect("failed to execute process");
+        let status = timeout::wait(&mut child, limit).expect("failed to execute process")?;
+        return Some(status.success());
+    }
+
+    let (success, output) = run_captured_within(cmd, limit)?;
+    if !success {
+        explain::explain(&output);
+    }
+
+    Some(success)
+}
+
+/// Run `cmd`, echoing its stdout and stderr as it runs, and return the
+/// combined output alongside whether it succeeded.
+fn run_captured(cmd: &mut Command) -> (bool, String) {
+    run_captured_within(cmd, None).unwrap_or_default()
+}
+
+/// Like `run_captured`, but kill the command once it runs longer than
+/// `limit`. None when it was killed.
+fn run_captured_within(cmd: &mut Command, limit: Option<Duration>) -> Option<(bool, String)> {
+    if limit.is_some() {
+        timeout::isolate(cmd);
+    }
+
+    let spawned = cmd.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn();
+    let mut child = match spawned {
+        Ok(child) => child,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let output = format!("{}: not found\n", cmd.get_program().to_string_lossy());
+            eprint!("{output}");
+            return Some((false, output));
+        }
+        Err(e) => panic!("failed to execute process: {e}"),
+    };
+
+    let captured = Arc::new(Mutex::new(String::new()));
+    let stdout = child.stdout.take().unwrap();
+    let stderr = child.stderr.take().unwrap();
+
+    let out_capture = Arc::clone(&captured);
+    let out_thread = thread::spawn(move || {
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            println!("{line}");
+            out_capture.lock().unwrap().push_str(&format!("{line}\n"));
+        }
+    });
+
+    let err_capture = Arc::clone(&captured);
+    let err_thread = thread::spawn(move || {
+        for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+            eprintln!("{line}");
+            err_capture.lock().unwrap().push_str(&format!("{line}\n"));
+        }
+    });
+
+    // Wait before joining, the readers only finish once the pipes close,
+    // which for a hung command is when it gets killed.
+    let status = match limit {
+        Some(limit) => timeout::wait(&mut child, limit),
+        None => child.wait().map(Some),
+    };
+    let status = status.expect("failed to execute process");
+
+    out_thread.join().unwrap();
+    err_thread.join().unwrap();
+    io::stdout().flush().unwrap();
+
+    let output = captured.lock().unwrap().clone();
+    Some((status?.success(), output))
+}
+
+/// Directory for captured logs, created on first use.
+fn log_dir(artifacts: &str) -> String {
+    let dir = combine_artifact_path(artifacts, "/Logs");
+    create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn print_warnings_summary(output: &str) {
+    let counts = diagnostic::count_warnings(&diagnostic::parse(output));
+    let total: usize = counts.iter().map(|(_, count)| count).sum();
+
+    println!("Warnings summary: {total} warnings");
+    for (flag, count) in counts {
+        println!("{count:>8}  {flag}");
+    }
+}
+
+/// Arguments forwarded to the underlying build tool after `--`.
+fn native_build_args(cmds: &CmakeVars, artifacts: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let generator = read_cache_var(artifacts, "CMAKE_GENERATOR").unwrap_or_default();
+
+    if let Some(load) = cmds.max_load {
+        // Both Make and Ninja take `-l N` to hold off new jobs while the load
+        // average is above N. Other generators have no equivalent.
+        if generator.contains("Makefiles") || generator.starts_with("Ninja") {
+            args.push("-l".to_string());
+            args.push(load.to_string());
+        } else {
+            println!("--max-load is not supported by the '{generator}' generator, ignoring");
+        }
+    }
+
+    args
+}
+
+/// Number of commands recorded in Ninja's .ninja_log, None for other generators.
+fn ninja_log_entries(artifacts: &str) -> Option<usize> {
+    let log = File::open(combine_artifact_path(artifacts, "/.ninja_log")).ok()?;
+    let lines = BufReader::new(log).lines().map_while(Result::ok);
+
+    Some(lines.filter(|line| !line.starts_with('#')).count())
+}
+
+/// Number of object files compiled after the first `skip` entries of
+/// .ninja_log, leaving out link, archive and custom commands.
+fn ninja_compiled_since(artifacts: &str, skip: usize) -> Option<usize> {
+    let log = File::open(combine_artifact_path(artifacts, "/.ninja_log")).ok()?;
+    let lines = BufReader::new(log).lines().map_while(Result::ok);
+    // Entries are start, end, mtime, output and command hash, tab separated.
+    let compiled = lines
+        .filter(|line| !line.starts_with('#'))
+        .skip(skip)
+        .filter_map(|line| line.split('\t').nth(3).map(str::to_string))
+        .filter(|output| output.ends_with(".o") || output.ends_with(".obj"))
+        .count();
+
+    Some(compiled)
+}
+
+/// Report how many outputs the build step regenerated, Ninja appends one log
+/// entry per command it runs.
+fn rebuilt_outputs(before: Option<usize>, after: Option<usize>) -> Option<usize> {
+    let rebuilt = match (before.unwrap_or_default(), after?) {
+        // Ninja recompacts its log every so often, leaving nothing to compare.
+        (before, after) if after < before => {
+            println!("Rebuild detection skipped, Ninja recompacted its log");
+            return None;
+        }
+        (before, after) => after - before,
+    };
+
+    match rebuilt {
+        0 => println!("Build was a no-op, nothing needed rebuilding"),
+        count => println!("Build regenerated {count} outputs"),
+    }
+
+    Some(rebuilt)
+}
+
+/// Look up the value of a variable in the build directory's CMakeCache.txt.
+fn read_cache_var(artifacts: &str, name: &str) -> Option<String> {
+    let cache = File::open(combine_artifact_path(artifacts, "/CMakeCache.txt")).ok()?;
+    let prefix = format!("{name}:");
+
+    // Entries are written as NAME:TYPE=VALUE
+    BufReader::new(cache)
+        .lines()
+        .map_while(Result::ok)
+        .find(|line| line.starts_with(&prefix))
+        .and_then(|line| line.split_once('=').map(|(_, value)| value.to_string()))
+}
+
+fn build_cmake(cmds: &CmakeVars, artifacts: &String) -> bool {
+    target_cmake(cmds, &build_target(cmds, artifacts), artifacts)
+}
+
+fn build_target(cmds: &CmakeVars, artifacts: &str) -> String {
+    match cmds.all_target {
+        Some(ref all_target) => all_target.clone(),
+        None => default_all_target(&read_cache_var(artifacts, "CMAKE_GENERATOR").unwrap_or_default()).to_string(),
+    }
+}
+
+/// The generator's name for the target that builds everything.
+fn default_all_target(generator: &str) -> &'static str {
+    if generator.starts_with("Visual Studio") || generator == "Xcode" {
+        "ALL_BUILD"
+    } else {
+        "all"
+    }
+}
+
+fn coverage_cmake(cmds: &CmakeVars, artifacts: &String) -> bool {
+    target_cmake(cmds, "ExperimentalCoverage", artifacts)
+}
+
+fn install_cmake(cmds: &CmakeVars, artifacts: &String, prefix: Option<&str>) -> bool {
+    let parallel = install_parallel(cmds);
+    if !parallel && prefix.is_none() {
+        return target_cmake(cmds, "install", artifacts);
+    }
+
+    run_step(cmds, &mut install_command(cmds, artifacts, prefix, parallel))
+}
+
+fn install_parallel(cmds: &CmakeVars) -> bool {
+    cmds.install_parallel
+        && match cmake_version() {
+            Some(version) if version >= (3, 31) => true,
+            _ => {
+                println!("Parallel install requires CMake 3.31+, installing serially");
+                false
+            }
+        }
+}
+
+/// The `cmake --install` command, installing the default prefix unless given one.
+fn install_command(cmds: &CmakeVars, artifacts: &str, prefix: Option<&str>, parallel: bool) -> Command {
+    let mut cmd = Command::new("cmake");
+    cmd.arg("--install").arg(artifacts);
+    if let Some(ref config) = cmds.config {
+        cmd.arg("--config").arg(config);
+    }
+    if let Some(prefix) = prefix {
+        cmd.arg("--prefix").arg(prefix);
+    }
+    if parallel {
+        cmd.arg("--parallel");
+        if let Some(count) = job_count(cmds) {
+            cmd.arg(count.to_string());
+        }
+    }
+    set_profile_env(cmds, &mut cmd);
+
+    cmd
+}
+
+/// Set the toolchain profile's environment, which matters beyond the
+/// configure, e.g. CCACHE_SLOPPINESS while building.
+fn set_profile_env(cmds: &CmakeVars, cmd: &mut Command) {
+    if let Some(ref toolchain) = cmds.toolchain {
+        cmd.envs(&toolchain.env);
+    }
+}
+
+fn compiler_cache_tool(cmds: &CmakeVars) -> CompilerCache {
+    cmds.compiler_cache.unwrap_or(CompilerCache::Ccache)
+}
+
+fn compiler_cache_stats(cmds: &CmakeVars) -> Option<compiler_cache::Stats> {
+    let tool = compiler_cache_tool(cmds);
+    compiler_cache::stats(tool.program(), tool.dir_var(), cmds.compiler_cache_dir.as_deref())
+}
+
+/// The job count --jobs asks for, saying what auto detected.
+fn job_count(cmds: &CmakeVars) -> Option<u32> {
+    let jobs = cmds.jobs?;
+    let count = jobs.count();
+    if jobs == jobs::Jobs::Auto {
+        println!("--jobs auto: using {count} jobs");
+    }
+    Some(count)
+}
+
+/// Report the package config files the install wrote, failing if there are none.
+fn check_install_export(artifacts: &str) -> bool {
+    let manifest_path = combine_artifact_path(artifacts, "/install_manifest.txt");
+    let Ok(manifest) = fs::read_to_string(&manifest_path) else {
+        eprintln!("{manifest_path} not found");
+        return false;
+    };
+
+    // find_package(Foo) looks for FooConfig.cmake or foo-config.cmake.
+    let configs: Vec<(&Path, &str)> = manifest
+        .lines()
+        .filter_map(|line| {
+            let path = Path::new(line);
+            let name = path.file_name()?.to_str()?;
+            let package = name.strip_suffix("Config.cmake").or_else(|| name.strip_suffix("-config.cmake"))?;
+            Some((path, package))
+        })
+        .collect();
+    if configs.is_empty() {
+        eprintln!("The install has no package config, check the project's install(EXPORT) rules");
+        return false;
+    }
+
+    for (config, package) in configs {
+        let dir = config.parent().unwrap_or(config);
+        println!("Installed package config {}, use {package}_DIR={}", config.display(), dir.display());
+    }
+
+    true
+}
+
+fn split_debug_info(artifacts: &str) -> bool {
+    let objcopy = read_cache_var(artifacts, "CMAKE_OBJCOPY")
+        .filter(|objcopy| !objcopy.is_empty())
+        .unwrap_or_else(|| "objcopy".to_string());
+    split_debug::split(artifacts, &objcopy)
+}
+
+/// Run `smoke_cmd` against `prefix`, or the cache's CMAKE_INSTALL_PREFIX.
+fn smoke_test(smoke_cmd: &str, artifacts: &str, prefix: Option<&str>) -> bool {
+    let Some(prefix) = prefix.map(str::to_string).or_else(|| read_cache_var(artifacts, "CMAKE_INSTALL_PREFIX")) else {
+        eprintln!("CMAKE_INSTALL_PREFIX not found in CMakeCache.txt");
+        return false;
+    };
+
+    let mut cmd = shell_command(smoke_cmd);
+    let lib_dirs = [format!("{prefix}/lib"), format!("{prefix}/lib64")];
+    let lib_var = match () {
+        _ if cfg!(target_os = "macos") => Some("DYLD_LIBRARY_PATH"),
+        _ if cfg!(unix) => Some("LD_LIBRARY_PATH"),
+        _ => None,
+    };
+    let env_set = prepend_env_paths(&mut cmd, "PATH", &[format!("{prefix}/bin")]).and_then(|()| match lib_var {
+        Some(var) => prepend_env_paths(&mut cmd, var, &lib_dirs),
+        None => Ok(()),
+    });
+    if let Err(e) = env_set {
+        eprintln!("Smoke test can't set up its environment: {e}");
+        return false;
+    }
+
+    println!("Smoke testing install in {prefix}: {smoke_cmd}");
+    let status = cmd.status().expect("failed to execute process").success();
+    if !status {
+        eprintln!("Smoke test failed: {smoke_cmd}");
+    }
+
+    status
+}
+
+fn notify(notify_cmd: &str, steps: &Steps, duration_secs: u64) {
+    let failed_step = steps.results.iter().find(|result| !result.success).map(|result| result.name.as_str());
+    let status = match steps.status {
+        true => "success",
+        false => "failure",
+    };
+
+    let notified = shell_command(notify_cmd)
+        .env("CLI_ASSIST_STATUS", status)
+        .env("CLI_ASSIST_FAILED_STEP", failed_step.unwrap_or_default())
+        .env("CLI_ASSIST_DURATION_SECS", duration_secs.to_string())
+        .status();
+    match notified {
+        Ok(exit) if exit.success() => {}
+        Ok(exit) => eprintln!("--notify command failed with {exit}"),
+        Err(e) => eprintln!("--notify command failed to start: {e}"),
+    }
+}
+
+/// Run a user supplied command line through the platform shell.
+fn shell_command(cmd_line: &str) -> Command {
+    let (shell, flag) = match cfg!(windows) {
+        true => ("cmd", "/C"),
+        false => ("sh", "-c"),
+    };
+
+    let mut cmd = Command::new(shell);
+    cmd.arg(flag).arg(cmd_line);
+    cmd
+}
+
+/// Set `var` for `cmd` to `paths` followed by the entries `var` already has.
+fn prepend_env_paths(cmd: &mut Command, var: &str, paths: &[String]) -> Result<(), String> {
+    let list = prepend_path_list(paths, env::var_os(var).as_deref()).map_err(|e| format!("{var}: {e}"))?;
+    cmd.env(var, list);
+    Ok(())
+}
+
+/// `paths` followed by the entries of `existing`, a native path list.
+fn prepend_path_list(paths: &[String], existing: Option<&OsStr>) -> Result<String, String> {
+    let mut list = paths.to_vec();
+    if let Some(existing) = existing {
+        list.extend(env::split_paths(existing).map(|path| path.to_string_lossy().into_owned()));
+    }
+    join_path_list(&list, PATH_LIST_SEPARATOR)
+}
+
+/// The (major, minor) version reported by `cmake --version`.
+fn cmake_version() -> Option<(u32, u32)> {
+    let output = Command::new("cmake").arg("--version").output().ok()?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    let regex = Regex::new(r"cmake version (\d+)\.(\d+)").unwrap();
+    let caps = regex.captures(&text)?;
+
+    Some((caps[1].parse().ok()?, caps[2].parse().ok()?))
+}
+
+fn clean_cmake(cmds: &CmakeVars, artifacts: &String) -> bool {
+    target_cmake(cmds, "clean", artifacts)
+}
+
+fn find_cpp_files(include_patterns: &[String], exclude_patterns: &[String], repo_root: String) -> Vec<String> {
+    // Search for .clang-tidy file
+    let mut glob_path = repo_root.to_owned();
+    glob_path.push_str("/**/*.cpp");
+
+    let all_cpp_glob = glob(glob_path.as_str()).expect("Failed to read glob pattern");
+    let mut all_cpp_files = Vec::<String>::new();
+    let compile = |patterns: &[String]| -> Vec<Regex> {
+        patterns
+            .iter()
+            .filter(|pattern| !pattern.is_empty())
+            .map(|pattern| Regex::new(pattern).unwrap())
+            .collect()
+    };
+    let includes = compile(include_patterns);
+    let excludes = compile(exclude_patterns);
+    for file in all_cpp_glob {
+        let file = file.expect("Invalid file found in cpp glob").into_os_string().into_string().expect("Pathbuf into String");
+        let abs_file = path::absolute(&file).unwrap().to_string_lossy().into_owned();
+
+        let matches = |regexes: &[Regex]| {
+            regexes
+                .iter()
+                .any(|regex| regex.is_match(file.as_str()) || regex.is_match(abs_file.as_str()))
+        };
+
+        // No includes means every file is included.
+        let included = includes.is_empty() || matches(&includes);
+        if included && !matches(&excludes) {
+            all_cpp_files.push(file.clone());
+        }
+    }
+
+    all_cpp_files
+}
+
+/// Keep `limit` of `files`, either the first ones or a random sample.
+fn limit_files(mut files: Vec<String>, limit: usize, sample: bool) -> Vec<String> {
+    if sample {
+        // RandomState is seeded per process, which is all the randomness a
+        // sample needs.
+        let state = RandomState::new();
+        files.sort_by_cached_key(|file| state.hash_one(file));
+    } else {
+        files.sort();
+    }
+
+    files.truncate(limit);
+    files
+}
+
+/// Generated and vendored sources that tidy skips unless --no-default-excludes.
+fn default_tidy_excludes(artifacts: &str) -> Result<Vec<String>, String> {
+    let build_dir = path::absolute(artifacts).map_err(|e| format!("Failed to resolve {artifacts}: {e}"))?;
+    Ok(vec![
+        format!("^{}/", regex::escape(&build_dir.to_string_lossy())),
+        "/_deps/".to_string(),
+        "/third_party/".to_string(),
+    ])
+}
+
+fn clang_tidy(cmds: &CmakeVars, artifacts: &str) -> bool {
+    let found = Command::new(&cmds.clang_tidy_bin).arg("--version").output().is_ok();
+    if !found {
+        eprintln!("{}: not found, set --clang-tidy-bin or CLANG_TIDY_BIN", cmds.clang_tidy_bin);
+        return false;
+    }
+
+    let repo_root = env::var("REPO_ROOT").expect("REPO_ROOT not set.");
+
+    // Search for .clang-tidy file
+    let cfg_loc = combine_artifact_path(&repo_root, "/**/.clang-tidy");
+    let cfg_loc = glob(cfg_loc.as_str())
+        .expect("Failed to find clang-tidy config")
+        .next()
         .unwrap() // Unwrap option
         .unwrap() // Unwrap result
         .into_os_string()
@@ -207,51 +2015,167 @@ fn clang_tidy(artifacts: &String) -> bool {
         .unwrap();
     
     // Scan for files that aren't excluded
-    let tidy_exclude_dirs = match env::var("TIDY_EXCLUDE") {
-        Ok(val) => val,
-        Err(_) => String::default(),
+    let mut tidy_excludes: Vec<String> = env::var("TIDY_EXCLUDE")
+        .unwrap_or_default()
+        .split(' ')
+        .map(str::to_string)
+        .collect();
+    if !cmds.no_default_excludes {
+        match default_tidy_excludes(artifacts) {
+            Ok(excludes) => tidy_excludes.extend(excludes),
+            Err(e) => {
+                eprintln!("{e}");
+                return false;
+            }
+        }
+    }
+    
+    let mut cpp_files = find_cpp_files(&cmds.tidy_include, &tidy_excludes, repo_root.clone());
+    if let Some(limit) = cmds.tidy_limit {
+        let found = cpp_files.len();
+        cpp_files = limit_files(cpp_files, limit, cmds.tidy_sample);
+        println!("Analysing {} of {found} files", cpp_files.len());
+    }
+
+    let mut fixes_file = combine_artifact_path(artifacts, "/ClangTidy");
+    if cmds.tidy_clean && Path::new(&fixes_file).exists() {
+        if let Err(e) = remove_dir_all(&fixes_file) {
+            eprintln!("Failed to remove {fixes_file}: {e}");
+            return false;
+        }
+    }
+    create_dir_all(&fixes_file).unwrap();
+    fixes_file.push_str("/clang-tidy-fixes.yaml");
+
+    let (stdout, success) = match cmds.tidy_cache_dir {
+        Some(ref cache_dir) => match tidy_cache::run(cmds, cache_dir, artifacts, &cfg_loc, &cpp_files) {
+            Ok(result) => result,
+            Err(e) => {
+                eprintln!("Tidy cache {cache_dir}: {e}");
+                return false;
+            }
+        },
+        None => {
+            // Fixes from an earlier cached run would be applied alongside this run's.
+            let cached_fixes = combine_artifact_path(artifacts, "/ClangTidy/cached-fixes");
+            if Path::new(&cached_fixes).exists() {
+                if let Err(e) = remove_dir_all(&cached_fixes) {
+                    eprintln!("Failed to remove {cached_fixes}: {e}");
+                    return false;
+                }
+            }
+            let output = run_tidy(cmds, artifacts, &cfg_loc, &fixes_file, &cpp_files);
+            if !output.status.success() && cmds.explain_failure {
+                explain::explain(&String::from_utf8_lossy(&output.stderr));
+            }
+            (output.stdout, output.status.success())
+        }
+    };
+    
+    // Capture output to clang-tidy.log
+    let out_file = combine_artifact_path(artifacts, "/ClangTidy/clang-tidy.log");
+    make_and_write_file(out_file, &stdout);
+
+    let diagnostics = diagnostic::parse(&String::from_utf8_lossy(&stdout));
+    if let Some(ref json_path) = cmds.tidy_json {
+        let json = serde_json::to_string_pretty(&diagnostics).unwrap();
+        make_and_write_file(json_path.clone(), json.as_bytes());
+    }
+
+    // With a baseline only new diagnostics decide the result.
+    let success = match cmds.tidy_baseline {
+        Some(ref baseline_path) => check_tidy_baseline(cmds, baseline_path, &diagnostics, &repo_root),
+        None => success,
+    };
+
+    // Search output for error: or warning:
+    let search_output = search_tidy(&stdout);
+
+    // Write reduced to clang-tidy-err.log
+    let mut out_file = artifacts.to_owned();
+    out_file.push_str("/ClangTidy/clang-tidy-err.log");
+    make_and_write_file(out_file, search_output.as_bytes());
+
+    success
+}
+
+fn check_tidy_baseline(cmds: &CmakeVars, baseline_path: &str, diagnostics: &[Diagnostic], repo_root: &str) -> bool {
+    let root = path::absolute(repo_root).expect("Failed to resolve REPO_ROOT");
+    let current = tidy_baseline::from_diagnostics(diagnostics, &root.to_string_lossy());
+
+    if cmds.tidy_write_baseline {
+        return match tidy_baseline::write(baseline_path, &current) {
+            Ok(()) => {
+                println!("Wrote {} accepted diagnostics to {baseline_path}", current.values().sum::<usize>());
+                true
+            }
+            Err(e) => {
+                eprintln!("{e}");
+                false
+            }
+        };
+    }
+
+    let baseline = match tidy_baseline::read(baseline_path) {
+        Ok(baseline) => baseline,
+        Err(e) => {
+            eprintln!("{e}");
+            return false;
+        }
     };
-    
-    let cpp_files = find_cpp_files(tidy_exclude_dirs, repo_root);
 
-    let mut cfg_file = "--config-file=".to_string();
-    cfg_file.push_str(cfg_loc.as_str());
+    let new = tidy_baseline::new_diagnostics(&current, &baseline);
+    if new.is_empty() {
+        println!("No tidy diagnostics beyond the baseline");
+        return true;
+    }
 
-    let mut fixes_file = combine_artifact_path(artifacts, "/ClangTidy");
-    create_dir_all(&fixes_file).unwrap();
-    fixes_file.push_str("/clang-tidy-fixes.yaml");
+    eprintln!("Tidy diagnostics not in the baseline:");
+    for (key, count) in new {
+        eprintln!("{count:>6}  {}: {} [{}]", key.file, key.message, key.check);
+    }
+    false
+}
+
+fn run_tidy(cmds: &CmakeVars, artifacts: &str, cfg_loc: &str, fixes_file: &str, files: &[String]) -> Output {
+    let mut cfg_file = "--config-file=".to_string();
+    cfg_file.push_str(cfg_loc);
 
     let mut fix_file = "--export-fixes=".to_string();
-    fix_file.push_str(fixes_file.as_str());
+    fix_file.push_str(fixes_file);
 
     // Call tool
-    let output = Command::new("clang-tidy")
+    Command::new(&cmds.clang_tidy_bin)
         .arg("-p")
         .arg(artifacts)
         .arg(cfg_file)
         .arg("--format-style=file")
         .arg(fix_file)
-        .args(cpp_files)
+        .args(files)
         .output()
-        .expect("failed to execute process");
-    
-    // Capture output to clang-tidy.log
-    let out_file = combine_artifact_path(artifacts, "/ClangTidy/clang-tidy.log");
-    make_and_write_file(out_file, &output.stdout);
-
-    // Search output for error: or warning:
-    let search_output = search_tidy(&output.stdout);
+        .expect("failed to execute process")
+}
 
-    // Write reduced to clang-tidy-err.log
-    let mut out_file = artifacts.clone();
-    out_file.push_str("/ClangTidy/clang-tidy-err.log");
-    make_and_write_file(out_file, search_output.as_bytes());
+fn tidy_apply_fixes(cmds: &CmakeVars, artifacts: &str) -> bool {
+    // Every exported YAML under ClangTidy is picked up, including cached ones.
+    let fixes_dir = combine_artifact_path(artifacts, "/ClangTidy");
+    if !Path::new(&fixes_dir).exists() {
+        eprintln!("{fixes_dir} not found, run --tidy first to export fixes");
+        return false;
+    }
 
-    output.status.success()
+    let bin = &cmds.clang_apply_replacements_bin;
+    match Command::new(bin).arg(&fixes_dir).status() {
+        Ok(status) => status.success(),
+        Err(_) => {
+            eprintln!("{bin}: not found, set --clang-apply-replacements-bin or CLANG_APPLY_REPLACEMENTS_BIN");
+            false
+        }
+    }
 }
 
-fn combine_artifact_path(artifacts: &String, text: &str) -> String {
-    let mut out_file = artifacts.clone();
+fn combine_artifact_path(artifacts: &str, text: &str) -> String {
+    let mut out_file = artifacts.to_owned();
     out_file.push_str(text);
 
     out_file
@@ -264,7 +2188,7 @@ fn search_tidy(text: &[u8]) -> String {
         let line = line.unwrap();
         if regex.is_match(line.as_str()) {
             search_output.push_str(line.as_str());
-            search_output.push_str("\n");
+            search_output.push('\n');
         }
     }
 
@@ -276,14 +2200,659 @@ fn make_and_write_file(path: String, text: &[u8]) {
     file.write_all(text).unwrap();
 }
 
-fn test_cmake(artifacts: &String) -> bool {
-    Command::new("ctest")
-        .arg("--test-dir")
+fn test_cmake(cmds: &CmakeVars, artifacts: &String) -> bool {
+    // A CI run that finds no tests mustn't pass.
+    if (cmds.require_tests || cmds.ci) && !require_tests(cmds, artifacts) {
+        return false;
+    }
+
+    if let Some(ref script) = cmds.ctest_script {
+        return ctest_script(cmds, script);
+    }
+
+    if cmds.test_by_label {
+        return test_by_label(cmds, artifacts);
+    }
+
+    let report_dir = report_dir(cmds, artifacts);
+    create_dir_all(&report_dir).unwrap();
+
+    let mut shard_filter = None;
+    if let Some((index, total)) = cmds.test_shard {
+        match shard_regex(cmds, artifacts, index, total) {
+            Ok(Some(regex)) => shard_filter = Some(regex),
+            Ok(None) => return true,
+            Err(e) => {
+                eprintln!("{e}");
+                return false;
+            }
+        }
+    }
+
+    // CTest resolves a relative report path against the test directory.
+    let junit_file = junit_report(cmds, artifacts);
+
+    let mut cmd = match test_command(cmds, artifacts, &junit_file, shard_filter) {
+        Ok(cmd) => cmd,
+        Err(e) => {
+            eprintln!("{e}");
+            return false;
+        }
+    };
+    let status = run_step(cmds, &mut cmd);
+
+    if cmds.test_wrapper.is_some() {
+        let dest = combine_artifact_path(&log_dir(artifacts), "/test-wrapper");
+        let count = memcheck::collect_logs(artifacts, &dest);
+        println!("Copied {count} wrapper logs to {dest}");
+    }
+
+    if let Some(count) = cmds.slowest_tests {
+        print_slowest_tests(&junit_file, count);
+    }
+
+    if cmds.test_format == TestFormat::Tap {
+        let tap_file = combine_artifact_path(&report_dir, &format!("/{}.tap", report_name(cmds)));
+        return write_tap_report(&junit_file, tap_file) && status;
+    }
+
+    status
+}
+
+/// One label group's test run, see `ctest::label_groups`.
+struct LabelRun {
+    name: String,
+    cmd: Command,
+    junit_file: String,
+    /// Shares tests with another group, see `ctest::shares_tests`.
+    exclusive: bool,
+}
+
+fn test_by_label(cmds: &CmakeVars, artifacts: &str) -> bool {
+    let tests = match ctest::list_tests(artifacts, cmds.config.as_deref()) {
+        Ok(tests) => tests,
+        Err(e) => {
+            eprintln!("{e}");
+            return false;
+        }
+    };
+
+    let report_dir = report_dir(cmds, &artifacts.to_string());
+    create_dir_all(&report_dir).unwrap();
+
+    let mut runs = Vec::new();
+    for label in ctest::label_groups(&tests) {
+        let name = label.clone().unwrap_or_else(|| "unlabeled".to_string());
+        let file_name: String = name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || "-_.".contains(c) { c } else { '_' })
+            .collect();
+        let junit_file = combine_artifact_path(&report_dir, &format!("/report-label-{file_name}.xml"));
+        let log_file = combine_artifact_path(&report_dir, &format!("/label-{file_name}.log"));
+
+        let mut cmd = match test_command(cmds, artifacts, &junit_file, None) {
+            Ok(cmd) => cmd,
+            Err(e) => {
+                eprintln!("{e}");
+                return false;
+            }
+        };
+        cmd.args(ctest::label_args(label.as_deref())).arg("-O").arg(log_file);
+        let exclusive = ctest::shares_tests(&tests, label.as_deref());
+        runs.push(LabelRun { name, cmd, junit_file, exclusive });
+    }
+
+    if runs.is_empty() {
+        println!("No tests to group by label");
+        return true;
+    }
+
+    let passed = match cmds.test_jobs {
+        1 => runs
+            .iter_mut()
+            .map(|run| {
+                println!("Testing label {}", run.name);
+                run_step(cmds, &mut run.cmd)
+            })
+            .collect(),
+        jobs => run_label_groups(&mut runs, jobs as usize),
+    };
+
+    println!("Tests by label:");
+    println!("    {:<20} {:>6} {:>6}  RESULT", "LABEL", "TESTS", "FAILED");
+    for (run, passed) in runs.iter().zip(&passed) {
+        let (total, failed) = match junit::read_report(&run.junit_file) {
+            Ok(report) => {
+                let failed = report.cases.iter().filter(|case| case.outcome() == junit::Outcome::Failed).count();
+                (report.cases.len().to_string(), failed.to_string())
+            }
+            Err(_) => ("?".to_string(), "?".to_string()),
+        };
+        let result = if *passed { "PASS" } else { "FAIL" };
+        println!("    {:<20} {total:>6} {failed:>6}  {result}", run.name);
+    }
+    println!("Label reports in {report_dir}");
+
+    passed.iter().all(|passed| *passed)
+}
+
+/// Run the label groups `jobs` at a time, then the exclusive ones one by
+/// one so no test runs twice at once. Their output is captured and printed
+/// as each finishes, so groups don't interleave.
+fn run_label_groups(runs: &mut [LabelRun], jobs: usize) -> Vec<bool> {
+    let passed = Mutex::new(vec![false; runs.len()]);
+    let (exclusive, concurrent): (Vec<_>, Vec<_>) = runs.iter_mut().enumerate().partition(|(_, run)| run.exclusive);
+    let queue = Mutex::new(concurrent.into_iter());
+
+    thread::scope(|scope| {
+        for _ in 0..jobs {
+            scope.spawn(|| loop {
+                let Some((idx, run)) = queue.lock().unwrap().next() else {
+                    break;
+                };
+                let success = run_label_captured(run);
+                passed.lock().unwrap()[idx] = success;
+            });
+        }
+    });
+
+    let mut passed = passed.into_inner().unwrap();
+    for (idx, run) in exclusive {
+        passed[idx] = run_label_captured(run);
+    }
+    passed
+}
+
+fn run_label_captured(run: &mut LabelRun) -> bool {
+    let output = run.cmd.output().expect("failed to execute process");
+
+    let _stdout = io::stdout().lock();
+    println!("Testing label {}", run.name);
+    print!("{}", String::from_utf8_lossy(&output.stdout));
+    eprint!("{}", String::from_utf8_lossy(&output.stderr));
+    output.status.success()
+}
+
+fn require_tests(cmds: &CmakeVars, artifacts: &str) -> bool {
+    let count = match ctest::list_tests(artifacts, cmds.config.as_deref()) {
+        Ok(tests) => tests.len(),
+        Err(e) => {
+            eprintln!("{e}");
+            return false;
+        }
+    };
+
+    println!("CTest discovered {count} tests");
+    if count == 0 {
+        eprintln!("--require-tests: no tests in {artifacts}, is testing enabled and are the tests built?");
+    }
+    count > 0
+}
+
+fn test_command(cmds: &CmakeVars, artifacts: &str, junit_file: &str, shard_filter: Option<String>) -> Result<Command, String> {
+    let mut cmd = Command::new("ctest");
+    cmd.arg("--test-dir")
         .arg(artifacts)
         .arg("--output-junit")
-        .arg("report.xml")
-        .arg("--output-on-failure")
-        .status()
-        .expect("failed to execute process")
-        .success()
-}
\ No newline at end of file
+        .arg(junit_file)
+        .arg("--output-on-failure");
+
+    // Multi-config trees need to be told which configuration to test.
+    if let Some(ref config) = cmds.config {
+        cmd.arg("-C").arg(config);
+    }
+
+    if let Some(regex) = shard_filter {
+        cmd.arg("-R").arg(regex);
+    }
+
+    set_profile_env(cmds, &mut cmd);
+    if let Some(ref env_file) = cmds.test_env_file {
+        cmd.envs(read_env_file(env_file)?);
+    }
+
+    if let Some(ref suppressions) = cmds.sanitize_suppressions {
+        set_suppressions(&mut cmd, &cmds.sanitize, suppressions);
+    }
+
+    if let Some(ref wrapper) = cmds.test_wrapper {
+        cmd.arg("-T").arg("memcheck").args(memcheck::overwrite_args(wrapper));
+    }
+
+    Ok(cmd)
+}
+
+fn run_fuzzer(cmds: &CmakeVars, target: &str, artifacts: &str) -> bool {
+    let corpus = cmds
+        .fuzz_corpus
+        .clone()
+        .unwrap_or_else(|| combine_artifact_path(artifacts, &format!("/fuzz-corpus/{target}")));
+    let crash_dir = combine_artifact_path(&log_dir(artifacts), &format!("/fuzz-{target}"));
+    fuzz::run(artifacts, target, &corpus, cmds.fuzz_time, &crash_dir)
+}
+
+fn submit_cdash(cmds: &CmakeVars, artifacts: &str) -> bool {
+    if !Path::new(&combine_artifact_path(artifacts, "/DartConfiguration.tcl")).exists() {
+        eprintln!("--submit needs a dashboard setup, include(CTest) in the project and reconfigure");
+        return false;
+    }
+
+    let repo_root = env::var("REPO_ROOT").unwrap_or_else(|_| ".".to_string());
+    let submit_url = cmds.cdash_url.clone().or_else(|| cdash::configured_submit_url(&repo_root));
+
+    let dashboard = |actions: &[&str]| {
+        let mut cmd = Command::new("ctest");
+        cmd.arg("--test-dir").arg(artifacts).arg("-M").arg("Experimental");
+        for action in actions {
+            cmd.arg("-T").arg(action);
+        }
+        if let Some(ref config) = cmds.config {
+            cmd.arg("-C").arg(config);
+        }
+        if let Some(ref url) = cmds.cdash_url {
+            cmd.arg("--overwrite").arg(format!("SubmitURL={url}"));
+        }
+        cmd
+    };
+
+    // Failing tests still get submitted, only a failed submit fails the step.
+    run_step(cmds, &mut dashboard(&["Start", "Test"]));
+
+    let submitted = dashboard(&["Submit"]).status().expect("failed to execute process").success();
+    if !submitted {
+        eprintln!("Submitting to CDash failed");
+        return false;
+    }
+
+    match submit_url {
+        Some(url) => println!("Submitted to CDash, see {}", cdash::dashboard_url(&url)),
+        None => println!("Submitted to CDash"),
+    }
+    true
+}
+
+fn memcheck_cmake(cmds: &CmakeVars, artifacts: &str) -> bool {
+    let mut cmd = Command::new("ctest");
+    cmd.arg("--test-dir").arg(artifacts).arg("-T").arg("memcheck");
+    if let Some(ref config) = cmds.config {
+        cmd.arg("-C").arg(config);
+    }
+
+    // Defects don't fail ctest itself, the threshold below decides.
+    run_step(cmds, &mut cmd);
+
+    let dest = combine_artifact_path(&log_dir(artifacts), "/memcheck");
+    let count = memcheck::collect_logs(artifacts, &dest);
+    println!("Copied {count} memcheck logs to {dest}");
+
+    let counts = match memcheck::defect_counts(artifacts) {
+        Ok(counts) => counts,
+        Err(e) => {
+            eprintln!("{e}");
+            return false;
+        }
+    };
+
+    let total: u64 = counts.iter().map(|(_, count)| count).sum();
+    println!("Memcheck found {total} defects");
+    for (kind, count) in counts {
+        println!("{count:>8}  {kind}");
+    }
+
+    if total > cmds.memcheck_max_defects {
+        eprintln!("More than {} memcheck defects", cmds.memcheck_max_defects);
+        return false;
+    }
+
+    true
+}
+
+fn read_env_file(path: &str) -> Result<Vec<(String, String)>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Failed to read {path}: {e}"))?;
+
+    let mut vars = Vec::new();
+    for (idx, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        match line.split_once('=') {
+            Some((key, value)) if !key.trim().is_empty() => {
+                vars.push((key.trim().to_string(), value.trim().to_string()));
+            }
+            _ => return Err(format!("{path}:{}: expected KEY=VALUE, got '{line}'", idx + 1)),
+        }
+    }
+
+    Ok(vars)
+}
+
+/// Point each sanitizer's runtime options at `suppressions`, keeping any
+/// options already set in the environment.
+fn set_suppressions(cmd: &mut Command, sanitizers: &[Sanitizer], suppressions: &str) {
+    let suppressions = path::absolute(suppressions).expect("Failed to resolve suppressions file");
+
+    let mut vars: Vec<&str> = sanitizers.iter().map(|sanitizer| sanitizer.options_var()).collect();
+    vars.sort();
+    vars.dedup();
+    for var in vars {
+        let option = format!("suppressions={}", suppressions.display());
+        let value = match env::var(var) {
+            Ok(existing) if !existing.is_empty() => format!("{existing}:{option}"),
+            _ => option,
+        };
+        cmd.env(var, value);
+    }
+}
+
+fn ctest_script(cmds: &CmakeVars, script: &str) -> bool {
+    let mut cmd = Command::new("ctest");
+    cmd.arg("-S").arg(script);
+    if let Some(ref config) = cmds.config {
+        cmd.arg("-C").arg(config);
+    }
+
+    // ctest_test() in a script doesn't see --output-on-failure, but does
+    // honour the environment variable.
+    cmd.env("CTEST_OUTPUT_ON_FAILURE", "1");
+    run_step(cmds, &mut cmd)
+}
+
+/// Report file name without extension, shards each get their own.
+fn report_name(cmds: &CmakeVars) -> String {
+    match cmds.test_shard {
+        Some((index, total)) => format!("report-shard-{index}-of-{total}"),
+        None => "report".to_string(),
+    }
+}
+
+/// Absolute path of the JUnit report the test step writes.
+fn junit_report(cmds: &CmakeVars, artifacts: &String) -> String {
+    combine_artifact_path(&report_dir(cmds, artifacts), &format!("/{}.xml", report_name(cmds)))
+}
+
+/// The `-R` regex selecting the tests of this shard, None if it has none.
+fn shard_regex(cmds: &CmakeVars, artifacts: &str, index: usize, total: usize) -> Result<Option<String>, String> {
+    let names = ctest::list_tests(artifacts, cmds.config.as_deref())?
+        .into_iter()
+        .map(|test| test.name)
+        .collect();
+    let timings = cmds.test_timings.as_deref().map(ctest::read_timings).transpose()?;
+
+    let shards = ctest::partition(names, total, timings.as_ref());
+    let shard = &shards[index - 1];
+    println!("Test shard {index}/{total}: {} tests", shard.len());
+
+    Ok((!shard.is_empty()).then(|| ctest::exact_regex(shard)))
+}
+
+fn report_dir(cmds: &CmakeVars, artifacts: &String) -> String {
+    let dir = cmds.report_dir.as_ref().unwrap_or(artifacts);
+    path::absolute(dir)
+        .expect("Failed to resolve report directory")
+        .into_os_string()
+        .into_string()
+        .expect("Pathbuf into String")
+}
+
+/// The output of `ninja -t deps` for the build directory. When the generator
+/// isn't Ninja or Ninja fails, prints why and gives the status to exit with.
+fn ninja_deps_output(artifacts: &str, flag: &str) -> Result<Vec<u8>, bool> {
+    let generator = read_cache_var(artifacts, "CMAKE_GENERATOR").unwrap_or_default();
+    if !generator.starts_with("Ninja") {
+        println!("{flag} needs a Ninja generator, not '{generator}', nothing to do");
+        return Err(true);
+    }
+
+    let ninja = read_cache_var(artifacts, "CMAKE_MAKE_PROGRAM").unwrap_or_else(|| "ninja".to_string());
+    match Command::new(&ninja).arg("-C").arg(artifacts).arg("-t").arg("deps").output() {
+        Ok(output) if output.status.success() => Ok(output.stdout),
+        Ok(output) => {
+            eprintln!("ninja -t deps failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+            Err(false)
+        }
+        Err(e) => {
+            eprintln!("{ninja}: {e}");
+            Err(false)
+        }
+    }
+}
+
+fn dump_ninja_deps(artifacts: &str, target: &str) -> bool {
+    let output = match ninja_deps_output(artifacts, "--dump-ninja-deps") {
+        Ok(output) => output,
+        Err(status) => return status,
+    };
+
+    let log_file = combine_artifact_path(&log_dir(artifacts), "/ninja-deps.txt");
+    make_and_write_file(log_file.clone(), &output);
+
+    let deps = ninja_deps::parse(&String::from_utf8_lossy(&output));
+    let selected = deps.iter().filter(|dep| target.is_empty() || ninja_deps::belongs_to(&dep.output, target));
+    let mut count = 0;
+    for dep in selected {
+        count += 1;
+        println!("{} ({}): {} inputs", dep.output, dep.state, dep.inputs.len());
+        if !target.is_empty() {
+            for input in &dep.inputs {
+                println!("    {input}");
+            }
+        }
+    }
+
+    if count == 0 && !target.is_empty() {
+        println!("No recorded dependencies for '{target}', has it been built?");
+    }
+    println!("Full dependency listing in {log_file}");
+    true
+}
+
+fn depfile_check(artifacts: &str) -> bool {
+    let output = match ninja_deps_output(artifacts, "--depfile-check") {
+        Ok(output) => output,
+        Err(status) => return status,
+    };
+
+    let deps = ninja_deps::parse(&String::from_utf8_lossy(&output));
+    if deps.is_empty() {
+        println!("Ninja recorded no dependencies, build first");
+        return true;
+    }
+
+    // Suspect objects by target, an object is suspect when Ninja doesn't
+    // consider its deps valid or misses one of its source's own includes.
+    let mut suspects: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+    for dep in &deps {
+        let Some(target) = ninja_deps::target_of(&dep.output) else {
+            continue;
+        };
+        if dep.state != "VALID" {
+            suspects.entry(target).or_default().push(format!("{}: deps {}", dep.output, dep.state));
+        }
+        for header in ninja_deps::untracked_includes(dep, artifacts) {
+            suspects.entry(target).or_default().push(format!("{}: \"{header}\" not tracked", dep.output));
+        }
+    }
+
+    if suspects.is_empty() {
+        println!("All {} objects track their direct includes", deps.len());
+        return true;
+    }
+
+    println!("Targets likely to under-rebuild:");
+    for (target, problems) in &suspects {
+        println!("  {target}");
+        for problem in problems {
+            println!("    {problem}");
+        }
+    }
+    false
+}
+
+fn explain_var(artifacts: &str, name: &str) -> bool {
+    let cache_file = combine_artifact_path(artifacts, "/CMakeCache.txt");
+    let Ok(text) = fs::read_to_string(&cache_file) else {
+        eprintln!("No {cache_file}, configure first");
+        return false;
+    };
+
+    let Some(entry) = cache::find(&text, name) else {
+        eprintln!("{name} is not in {cache_file}");
+        let similar = cache::similar(&text, name);
+        if !similar.is_empty() {
+            eprintln!("Similar variables: {}", similar.join(", "));
+        }
+        return false;
+    };
+
+    let advanced = cache::find(&text, &format!("{name}-ADVANCED")).is_some_and(|entry| entry.value == "1");
+    let origin = match entry.kind.as_str() {
+        "INTERNAL" | "STATIC" => "CMake, for its own use",
+        _ if entry.set_by_user() => "the user",
+        _ => "the project",
+    };
+
+    println!("{}={}", entry.name, entry.value);
+    println!("    type:     {}{}", entry.kind, if advanced { " (advanced)" } else { "" });
+    println!("    help:     {}", if entry.help.is_empty() { "none" } else { &entry.help });
+    println!("    set by:   {origin}");
+    true
+}
+
+fn show_test_report(junit_file: &str) {
+    if !Path::new(junit_file).exists() {
+        eprintln!("No test report at {junit_file}, run --test first");
+        return;
+    }
+
+    match junit::read_report(junit_file) {
+        Ok(report) => print!("{}", junit::to_table(&report)),
+        Err(e) => eprintln!("{e}"),
+    }
+}
+
+fn print_slowest_tests(junit_file: &str, count: usize) {
+    let report = match junit::read_report(junit_file) {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("{e}");
+            return;
+        }
+    };
+
+    let mut timed: Vec<(&str, f64)> = report
+        .cases
+        .iter()
+        .filter_map(|case| case.time().map(|time| (case.name.as_str(), time)))
+        .collect();
+    if timed.is_empty() {
+        println!("The test report has no timings");
+        return;
+    }
+
+    timed.sort_by(|a, b| b.1.total_cmp(&a.1));
+    println!("Slowest tests:");
+    for (name, time) in timed.into_iter().take(count) {
+        println!("{time:>10.2}s  {name}");
+    }
+}
+
+fn write_tap_report(junit_file: &str, tap_file: String) -> bool {
+    // CTest only writes JUnit, so TAP is produced by converting that report.
+    match junit::read_report(junit_file) {
+        Ok(report) => {
+            make_and_write_file(tap_file, junit::to_tap(&report).as_bytes());
+            true
+        }
+        Err(e) => {
+            eprintln!("{e}");
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(paths: &[&str]) -> Vec<String> {
+        paths.iter().map(|path| path.to_string()).collect()
+    }
+
+    #[test]
+    fn default_all_target_follows_the_generator() {
+        assert_eq!(default_all_target("Ninja"), "all");
+        assert_eq!(default_all_target("Unix Makefiles"), "all");
+        assert_eq!(default_all_target("Visual Studio 17 2022"), "ALL_BUILD");
+        assert_eq!(default_all_target("Xcode"), "ALL_BUILD");
+    }
+
+    #[test]
+    fn build_target_reads_the_generator_from_the_cache() {
+        let build_dir = env::temp_dir().join(format!("cli_assist-build-target-{}", std::process::id()));
+        create_dir_all(&build_dir).unwrap();
+        fs::write(build_dir.join("CMakeCache.txt"), "CMAKE_GENERATOR:INTERNAL=Xcode\n").unwrap();
+
+        let cmds = CmakeVars::parse_from(["cmake"]);
+        let target = build_target(&cmds, &build_dir.to_string_lossy());
+        remove_dir_all(&build_dir).unwrap();
+        assert_eq!(target, "ALL_BUILD");
+    }
+
+    #[test]
+    fn ninja_compiled_since_counts_only_new_object_files() {
+        let build_dir = env::temp_dir().join(format!("cli_assist-ninja-log-{}", std::process::id()));
+        create_dir_all(&build_dir).unwrap();
+        let log = "# ninja log v5\n1\t2\t0\told.o\th\n3\t4\t0\ta.cpp.o\th\n5\t6\t0\tb.obj\th\n\
+                   7\t8\t0\tlibapp.a\th\n9\t10\t0\tapp\th\n11\t12\t0\tgen/version.h\th\n";
+        fs::write(build_dir.join(".ninja_log"), log).unwrap();
+
+        let compiled = ninja_compiled_since(&build_dir.to_string_lossy(), 1);
+        remove_dir_all(&build_dir).unwrap();
+        assert_eq!(compiled, Some(2));
+    }
+
+    #[test]
+    fn all_target_overrides_the_generator_default() {
+        let cmds = CmakeVars::parse_from(["cmake", "--all-target", "everything"]);
+        assert_eq!(build_target(&cmds, "/nonexistent"), "everything");
+    }
+
+    #[test]
+    fn join_path_list_uses_colons_on_unix() {
+        assert_eq!(join_path_list(&strings(&["/opt/a", "/opt/b"]), ':').unwrap(), "/opt/a:/opt/b");
+    }
+
+    #[test]
+    fn join_path_list_uses_semicolons_on_windows() {
+        let paths = strings(&["C:\\deps\\a", "C:\\deps\\b"]);
+        assert_eq!(join_path_list(&paths, ';').unwrap(), "C:\\deps\\a;C:\\deps\\b");
+    }
+
+    #[test]
+    fn join_path_list_rejects_paths_with_the_separator() {
+        assert!(join_path_list(&strings(&["/opt/a:/opt/b"]), ':').is_err());
+        assert!(join_path_list(&strings(&["C:\\a;C:\\b"]), ';').is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn prepend_path_list_keeps_every_existing_entry() {
+        let joined = prepend_path_list(&strings(&["/opt/x"]), Some(OsStr::new("/a:/b"))).unwrap();
+        assert_eq!(joined, "/opt/x:/a:/b");
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn prepend_path_list_keeps_every_existing_entry() {
+        let joined = prepend_path_list(&strings(&["C:\\x"]), Some(OsStr::new("C:\\a;C:\\b"))).unwrap();
+        assert_eq!(joined, "C:\\x;C:\\a;C:\\b");
+    }
+
+    #[test]
+    fn prepend_path_list_without_an_existing_value() {
+        let joined = prepend_path_list(&strings(&["/opt/x", "/opt/y"]), None).unwrap();
+        assert_eq!(joined, format!("/opt/x{PATH_LIST_SEPARATOR}/opt/y"));
+    }
+}
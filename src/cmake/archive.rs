@@ -0,0 +1,54 @@
+use std::{fs::{self, File}, path::Path};
+use flate2::{write::GzEncoder, Compression};
+use glob::glob;
+
+/// Object file extensions left out of an archive unless a full one is asked for.
+const OBJECT_EXTENSIONS: [&str; 2] = ["o", "obj"];
+
+/// Write the files of `artifacts` matching `pattern` (default: everything)
+/// into the tarball `out_path`.
+pub fn archive(artifacts: &str, out_path: &str, pattern: Option<&str>, full: bool) -> Result<(), String> {
+    let pattern = format!("{artifacts}/{}", pattern.unwrap_or("**/*"));
+    let out_file = Path::new(out_path);
+
+    let file = File::create(out_file).map_err(|e| format!("Failed to create {out_path}: {e}"))?;
+    let mut tarball = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+
+    let mut count = 0;
+    let entries = glob(&pattern).map_err(|e| format!("Invalid archive glob {pattern}: {e}"))?;
+    for entry in entries {
+        let path = entry.map_err(|e| format!("Failed to read {}: {}", e.path().display(), e.error()))?;
+        if !path.is_file() || same_file(&path, out_file) {
+            continue;
+        }
+
+        let is_object = path
+            .extension()
+            .is_some_and(|ext| OBJECT_EXTENSIONS.iter().any(|obj| ext == *obj));
+        if is_object && !full {
+            continue;
+        }
+
+        let name = path.strip_prefix(artifacts).unwrap_or(&path);
+        tarball
+            .append_path_with_name(&path, name)
+            .map_err(|e| format!("Failed to archive {}: {e}", path.display()))?;
+        count += 1;
+    }
+
+    tarball
+        .into_inner()
+        .and_then(|encoder| encoder.finish())
+        .map_err(|e| format!("Failed to finish {out_path}: {e}"))?;
+
+    let size = fs::metadata(out_file).map(|meta| meta.len()).unwrap_or_default();
+    println!("Archived {count} files to {out_path} ({size} bytes)");
+    Ok(())
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
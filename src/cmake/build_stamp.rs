@@ -0,0 +1,53 @@
+use std::fs;
+use crate::{hash::sha256_hex, repo::{git, is_cpp}};
+
+const STAMP_FILE: &str = ".cli_assist-build-stamp";
+
+/// Whether a change to `file` can change the build: C and C++ sources and
+/// headers, CMakeLists.txt, .cmake scripts and CMake presets.
+fn is_relevant(file: &str) -> bool {
+    let name = file.rsplit('/').next().unwrap_or(file);
+    is_cpp(file)
+        || name == "CMakeLists.txt"
+        || name.ends_with(".cmake")
+        || name == "CMakePresets.json"
+        || name == "CMakeUserPresets.json"
+}
+
+/// A digest of every relevant file in the work tree: the committed blobs,
+/// uncommitted changes to them and untracked ones, plus the CMakeCache.txt
+/// of `artifacts`, so reconfiguring with other settings rebuilds. None
+/// outside git.
+pub fn fingerprint(artifacts: &str) -> Option<String> {
+    let root = git(&["rev-parse", "--show-toplevel"])?;
+    let cache = fs::read(format!("{artifacts}/CMakeCache.txt")).unwrap_or_default();
+    let mut state = format!("CMakeCache.txt {}\n", sha256_hex(&cache));
+
+    // Committed content, by blob hash so unrelated commits don't count.
+    for line in git(&["-C", &root, "ls-tree", "-r", "HEAD"])?.lines() {
+        if line.split_once('\t').is_some_and(|(_, file)| is_relevant(file)) {
+            state.push_str(line);
+            state.push('\n');
+        }
+    }
+
+    // Run from the top level, ls-files only lists the current directory.
+    let changed = git(&["-C", &root, "diff", "--name-only", "HEAD"])?;
+    let untracked = git(&["-C", &root, "ls-files", "--others", "--exclude-standard"])?;
+    for file in changed.lines().chain(untracked.lines()).filter(|file| is_relevant(file)) {
+        // Reads fail for deleted files, which still change the digest.
+        let content = fs::read(format!("{root}/{file}")).unwrap_or_default();
+        state.push_str(&format!("{file} {}\n", sha256_hex(&content)));
+    }
+
+    Some(sha256_hex(state.as_bytes()))
+}
+
+pub fn read(artifacts: &str) -> Option<String> {
+    fs::read_to_string(format!("{artifacts}/{STAMP_FILE}")).ok()
+}
+
+pub fn write(artifacts: &str, fingerprint: &str) {
+    let path = format!("{artifacts}/{STAMP_FILE}");
+    fs::write(&path, fingerprint).unwrap_or_else(|_| panic!("Failed to write {path}"));
+}
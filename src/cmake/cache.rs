@@ -0,0 +1,64 @@
+/// The help string CMake gives variables passed with -D that the project
+/// never declared.
+const COMMAND_LINE_HELP: &str = "No help, variable specified on the command line.";
+
+/// One CMakeCache.txt entry, written as
+///
+/// ```text
+/// //Build shared libraries
+/// BUILD_SHARED_LIBS:BOOL=ON
+/// ```
+#[derive(Debug)]
+pub struct Entry {
+    pub name: String,
+    pub kind: String,
+    pub value: String,
+    pub help: String,
+}
+
+impl Entry {
+    /// Whether the value came from the user, through -D or a cache editor,
+    /// rather than the project's own defaults. CMake keeps no record of
+    /// edits, so this is a best guess from the help string and the type.
+    pub fn set_by_user(&self) -> bool {
+        self.help == COMMAND_LINE_HELP || self.kind == "UNINITIALIZED"
+    }
+}
+
+/// Find `name` in the text of a CMakeCache.txt.
+pub fn find(text: &str, name: &str) -> Option<Entry> {
+    let mut help: Vec<&str> = Vec::new();
+    for line in text.lines() {
+        if let Some(comment) = line.strip_prefix("//") {
+            help.push(comment);
+            continue;
+        }
+
+        let entry = line.split_once('=').and_then(|(key, value)| {
+            let (entry_name, kind) = key.split_once(':')?;
+            (entry_name.trim_matches('"') == name).then(|| Entry {
+                name: name.to_string(),
+                kind: kind.to_string(),
+                value: value.to_string(),
+                help: help.join(" "),
+            })
+        });
+        if entry.is_some() {
+            return entry;
+        }
+        help.clear();
+    }
+
+    None
+}
+
+/// Cache variables named like `name`, ignoring case, for suggestions.
+pub fn similar(text: &str, name: &str) -> Vec<String> {
+    let lower = name.to_lowercase();
+    text.lines()
+        .filter(|line| !line.starts_with("//") && !line.starts_with('#'))
+        .filter_map(|line| line.split_once(':').map(|(key, _)| key.trim_matches('"')))
+        .filter(|key| !key.ends_with("-ADVANCED") && key.to_lowercase().contains(&lower))
+        .map(|key| key.to_string())
+        .collect()
+}
@@ -0,0 +1,22 @@
+use std::fs;
+use regex::Regex;
+
+/// The submit URL set by the project's CTestConfig.cmake, either directly
+/// through CTEST_SUBMIT_URL or from the older CTEST_DROP_* variables.
+pub fn configured_submit_url(repo_root: &str) -> Option<String> {
+    let config = fs::read_to_string(format!("{repo_root}/CTestConfig.cmake")).ok()?;
+    let var = |name: &str| {
+        let regex = Regex::new(&format!(r#"set\s*\(\s*{name}\s+"?([^")\s]+)"?\s*\)"#)).unwrap();
+        regex.captures(&config).map(|caps| caps[1].to_string())
+    };
+
+    var("CTEST_SUBMIT_URL").or_else(|| {
+        let method = var("CTEST_DROP_METHOD").unwrap_or_else(|| "https".to_string());
+        Some(format!("{method}://{}{}", var("CTEST_DROP_SITE")?, var("CTEST_DROP_LOCATION")?))
+    })
+}
+
+/// The dashboard page matching a `.../submit.php?project=NAME` URL.
+pub fn dashboard_url(submit_url: &str) -> String {
+    submit_url.replacen("submit.php", "index.php", 1)
+}
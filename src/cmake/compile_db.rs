@@ -0,0 +1,217 @@
+use std::{
+    collections::BTreeSet,
+    env,
+    fs::{self, read_to_string},
+    path::{self, Path},
+};
+use serde::Deserialize;
+
+/// Compiler flags whose operand is the next argument when not attached.
+const TWO_PART_FLAGS: [&str; 16] = [
+    "-D", "-U", "-I", "-include", "-imacros", "-isystem", "-iquote", "-idirafter", "-isysroot", "-Xclang", "-x",
+    "-MF", "-MT", "-MQ", "-target", "-arch",
+];
+
+/// One entry of a `compile_commands.json` compilation database.
+#[derive(Deserialize, Debug)]
+pub struct Entry {
+    pub file: String,
+
+    #[serde(default)]
+    command: Option<String>,
+
+    #[serde(default)]
+    arguments: Option<Vec<String>>,
+
+    #[serde(default)]
+    output: Option<String>,
+}
+
+impl Entry {
+    /// The compiler invocation, split into arguments.
+    pub fn args(&self) -> Vec<String> {
+        match (&self.arguments, &self.command) {
+            (Some(args), _) => args.clone(),
+            (None, Some(cmd)) => split_command(cmd),
+            (None, None) => Vec::new(),
+        }
+    }
+
+    /// Only the flags of the invocation, without the compiler, inputs or
+    /// outputs. A flag with a separate operand, e.g. `-isystem DIR`, is kept
+    /// as one flag.
+    pub fn flags(&self) -> Vec<String> {
+        let mut flags = Vec::new();
+        let mut args = self.args().into_iter().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-o" => {
+                    args.next();
+                }
+                "-c" | "--" => {}
+                _ if TWO_PART_FLAGS.contains(&arg.as_str()) => match args.next() {
+                    Some(operand) => flags.push(format!("{arg} {operand}")),
+                    None => flags.push(arg),
+                },
+                _ if arg.starts_with('-') => flags.push(arg),
+                _ => {}
+            }
+        }
+
+        flags
+    }
+
+    /// A TU belongs to a CMake target when its object lands in `CMakeFiles/<target>.dir/`.
+    fn in_target(&self, target: &str) -> bool {
+        let obj_dir = format!("CMakeFiles/{target}.dir/");
+        self.output.as_deref().is_some_and(|out| out.contains(&obj_dir))
+            || self.args().iter().any(|arg| arg.contains(&obj_dir))
+    }
+}
+
+/// Split a `command` entry into arguments the way a POSIX shell would,
+/// honouring single and double quotes and backslash escapes.
+fn split_command(cmd: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut arg = String::new();
+    let mut in_arg = false;
+    let mut quote = None;
+    let mut chars = cmd.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some('\''), '\'') | (Some('"'), '"') => quote = None,
+            (Some('\''), _) => arg.push(c),
+            (Some(_), '\\') => match chars.next() {
+                Some(escaped @ ('"' | '\\' | '$' | '`')) => arg.push(escaped),
+                Some(other) => arg.extend(['\\', other]),
+                None => arg.push(c),
+            },
+            (None, '\\') => arg.extend(chars.next()),
+            (None, '\'' | '"') => quote = Some(c),
+            (None, _) if c.is_whitespace() => {
+                if in_arg {
+                    args.push(std::mem::take(&mut arg));
+                }
+                in_arg = false;
+                continue;
+            }
+            _ => arg.push(c),
+        }
+        in_arg = true;
+    }
+    if in_arg {
+        args.push(arg);
+    }
+
+    args
+}
+
+pub fn path(artifacts: &str) -> String {
+    format!("{artifacts}/compile_commands.json")
+}
+
+/// Point `<REPO_ROOT>/compile_commands.json` at the build's compile database,
+/// where clangd and other language servers look for it. Windows gets a copy
+/// as symlinks need extra privileges there.
+pub fn link_to_repo_root(artifacts: &str) -> bool {
+    let db_path = path::absolute(path(artifacts)).unwrap();
+    if !db_path.exists() {
+        eprintln!("{}: compile_commands.json not found", db_path.display());
+        return false;
+    }
+
+    let repo_root = env::var("REPO_ROOT").unwrap_or_else(|_| ".".to_string());
+    let link = Path::new(&repo_root).join("compile_commands.json");
+    if link.symlink_metadata().is_ok() {
+        fs::remove_file(&link).unwrap_or_else(|_| panic!("Failed to replace {}", link.display()));
+    }
+
+    #[cfg(unix)]
+    let linked = std::os::unix::fs::symlink(&db_path, &link);
+    #[cfg(not(unix))]
+    let linked = fs::copy(&db_path, &link).map(|_| ());
+
+    match linked {
+        Ok(_) => {
+            println!("Linked {} to {}", link.display(), db_path.display());
+            true
+        }
+        Err(e) => {
+            eprintln!("Failed to link {}: {e}", link.display());
+            false
+        }
+    }
+}
+
+pub fn read(artifacts: &str) -> Result<Vec<Entry>, String> {
+    let db_path = path(artifacts);
+    let text = read_to_string(&db_path).map_err(|_| {
+        format!("{db_path}: compile_commands.json not found, configure with CMAKE_EXPORT_COMPILE_COMMANDS=ON")
+    })?;
+
+    serde_json::from_str(&text).map_err(|e| format!("Failed to parse {db_path}: {e}"))
+}
+
+/// Print the distinct flags used by every TU of `filter`, which is either a
+/// CMake target name or a source file prefix.
+pub fn print_flags(artifacts: &str, filter: &str) -> bool {
+    let entries = match read(artifacts) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("{e}");
+            return false;
+        }
+    };
+
+    let matched: Vec<&Entry> = entries
+        .iter()
+        .filter(|entry| entry.in_target(filter) || entry.file.starts_with(filter))
+        .collect();
+
+    if matched.is_empty() {
+        eprintln!("No translation units match target or prefix '{filter}'");
+        return false;
+    }
+
+    let distinct: BTreeSet<String> = matched.iter().flat_map(|entry| entry.flags()).collect();
+    println!("Distinct flags for '{filter}' over {} translation units:", matched.len());
+    for flag in &distinct {
+        println!("    {flag}");
+    }
+
+    for entry in matched {
+        println!("\n{}", entry.file);
+        for flag in entry.flags() {
+            println!("    {flag}");
+        }
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(command: &str) -> Entry {
+        Entry { file: "a.cpp".to_string(), command: Some(command.to_string()), arguments: None, output: None }
+    }
+
+    #[test]
+    fn split_command_follows_shell_quoting() {
+        assert_eq!(
+            split_command(r#"c++ -DFOO="a b" '-DBAR=c d' -DQ=\"x\" -D'E'F  a.cpp"#),
+            ["c++", "-DFOO=a b", "-DBAR=c d", r#"-DQ="x""#, "-DEF", "a.cpp"]
+        );
+        assert_eq!(split_command(r#"cc "" -I"dir\\sub""#), ["cc", "", r"-Idir\sub"]);
+    }
+
+    #[test]
+    fn flags_keep_separate_operands_and_drop_inputs_and_outputs() {
+        let entry = entry("c++ -isystem /opt/inc -include pch.h -I inc -D X=1 -Xclang -ast-dump -x c++ -O2 -o a.o -c a.cpp");
+        assert_eq!(
+            entry.flags(),
+            ["-isystem /opt/inc", "-include pch.h", "-I inc", "-D X=1", "-Xclang -ast-dump", "-x c++", "-O2"]
+        );
+    }
+}
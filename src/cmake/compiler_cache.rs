@@ -0,0 +1,77 @@
+use std::process::Command;
+
+/// Cache hits and misses as the cache tool counts them.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Stats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl Stats {
+    /// What happened between `before` and `self`.
+    pub fn since(self, before: Stats) -> Stats {
+        Stats {
+            hits: self.hits.saturating_sub(before.hits),
+            misses: self.misses.saturating_sub(before.misses),
+        }
+    }
+
+    pub fn hit_rate(self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            total => self.hits as f64 * 100.0 / total as f64,
+        }
+    }
+}
+
+/// Read the counters of `program` (ccache or sccache), using the cache in
+/// `dir_var` = `dir` when given. None when the tool can't report them.
+pub fn stats(program: &str, dir_var: &str, dir: Option<&str>) -> Option<Stats> {
+    let mut cmd = Command::new(program);
+    match program {
+        "sccache" => cmd.arg("--show-stats").arg("--stats-format=json"),
+        _ => cmd.arg("--print-stats"),
+    };
+    if let Some(dir) = dir {
+        cmd.env(dir_var, dir);
+    }
+
+    let output = cmd.output().ok().filter(|output| output.status.success())?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    match program {
+        "sccache" => parse_sccache(&text),
+        _ => Some(parse_ccache(&text)),
+    }
+}
+
+/// Parse `ccache --print-stats`, tab separated `KEY VALUE` lines. ccache 4
+/// and ccache 3 name the hit counters differently.
+fn parse_ccache(text: &str) -> Stats {
+    let mut stats = Stats::default();
+    for (key, value) in text.lines().filter_map(|line| line.split_once('\t')) {
+        let value: u64 = value.trim().parse().unwrap_or_default();
+        match key {
+            "direct_cache_hit" | "preprocessed_cache_hit" | "cache_hit_direct" | "cache_hit_preprocessed" => {
+                stats.hits += value
+            }
+            "cache_miss" => stats.misses += value,
+            _ => {}
+        }
+    }
+
+    stats
+}
+
+/// Parse `sccache --show-stats --stats-format=json`, which counts hits and
+/// misses per language.
+fn parse_sccache(text: &str) -> Option<Stats> {
+    let json: serde_json::Value = serde_json::from_str(text).ok()?;
+    let total = |counter: &str| -> u64 {
+        json["stats"][counter]["counts"]
+            .as_object()
+            .map(|counts| counts.values().filter_map(|count| count.as_u64()).sum())
+            .unwrap_or_default()
+    };
+
+    Some(Stats { hits: total("cache_hits"), misses: total("cache_misses") })
+}
@@ -0,0 +1,170 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    process::Command,
+};
+use serde::Deserialize;
+use super::junit;
+
+/// The parts of `ctest --show-only=json-v1` this tool reads.
+#[derive(Deserialize, Debug)]
+struct TestInfo {
+    #[serde(default)]
+    tests: Vec<Test>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Test {
+    pub name: String,
+
+    #[serde(default)]
+    properties: Vec<Property>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Property {
+    name: String,
+    value: serde_json::Value,
+}
+
+impl Test {
+    /// The test's LABELS property, empty for unlabeled tests.
+    pub fn labels(&self) -> Vec<String> {
+        self.properties
+            .iter()
+            .filter(|property| property.name == "LABELS")
+            .filter_map(|property| property.value.as_array())
+            .flatten()
+            .filter_map(|label| label.as_str().map(str::to_string))
+            .collect()
+    }
+}
+
+/// The distinct labels of `tests` in name order, then None when some tests
+/// have no label at all.
+pub fn label_groups(tests: &[Test]) -> Vec<Option<String>> {
+    let labels: BTreeSet<String> = tests.iter().flat_map(Test::labels).collect();
+    let mut groups: Vec<Option<String>> = labels.into_iter().map(Some).collect();
+    if tests.iter().any(|test| test.labels().is_empty()) {
+        groups.push(None);
+    }
+
+    groups
+}
+
+/// Whether a test in the `label` group also runs in another group, so the
+/// two groups mustn't run at once. The unlabeled group never shares tests.
+pub fn shares_tests(tests: &[Test], label: Option<&str>) -> bool {
+    let Some(label) = label else {
+        return false;
+    };
+
+    tests.iter().map(Test::labels).any(|labels| labels.len() > 1 && labels.iter().any(|l| l == label))
+}
+
+/// CTest arguments selecting one `label_groups` group. -L and -LE take
+/// regexes, so labels are matched exactly and the unlabeled group is
+/// everything without a label.
+pub fn label_args(label: Option<&str>) -> [String; 2] {
+    match label {
+        Some(label) => ["-L".to_string(), format!("^{}$", regex::escape(label))],
+        None => ["-LE".to_string(), ".".to_string()],
+    }
+}
+
+/// List the tests CTest knows about in `artifacts` without running them.
+pub fn list_tests(artifacts: &str, config: Option<&str>) -> Result<Vec<Test>, String> {
+    let mut cmd = Command::new("ctest");
+    cmd.arg("--test-dir").arg(artifacts).arg("--show-only=json-v1");
+    if let Some(config) = config {
+        cmd.arg("-C").arg(config);
+    }
+
+    let output = cmd.output().map_err(|e| format!("Failed to run ctest: {e}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "ctest --show-only failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let info: TestInfo = serde_json::from_slice(&output.stdout)
+        .map_err(|e| format!("Failed to parse ctest test list: {e}"))?;
+    Ok(info.tests)
+}
+
+/// Parse an `INDEX/TOTAL` shard, where INDEX counts from 1.
+pub fn parse_shard(text: &str) -> Result<(usize, usize), String> {
+    let (index, total) = text.split_once('/').ok_or("expected INDEX/TOTAL, e.g. 1/4")?;
+    let index: usize = index.parse().map_err(|_| format!("invalid shard index '{index}'"))?;
+    let total: usize = total.parse().map_err(|_| format!("invalid shard total '{total}'"))?;
+
+    if index == 0 || index > total {
+        return Err(format!("shard index must be between 1 and {total}"));
+    }
+
+    Ok((index, total))
+}
+
+/// Historical test durations, read from a previous JUnit report.
+pub fn read_timings(path: &str) -> Result<HashMap<String, f64>, String> {
+    let report = junit::read_report(path)?;
+    Ok(report
+        .cases
+        .iter()
+        .filter_map(|case| case.time().map(|time| (case.name.clone(), time)))
+        .collect())
+}
+
+/// Split `names` into `total` shards. With timings, each test goes to the
+/// currently shortest shard, longest tests first; tests without a timing
+/// count as the average. Without timings, tests are dealt out round-robin
+/// in name order.
+pub fn partition(mut names: Vec<String>, total: usize, timings: Option<&HashMap<String, f64>>) -> Vec<Vec<String>> {
+    let mut shards = vec![Vec::new(); total];
+    names.sort();
+
+    let Some(timings) = timings.filter(|timings| !timings.is_empty()) else {
+        for (idx, name) in names.into_iter().enumerate() {
+            shards[idx % total].push(name);
+        }
+        return shards;
+    };
+
+    let average = timings.values().sum::<f64>() / timings.len() as f64;
+    let duration = |name: &String| timings.get(name).copied().unwrap_or(average);
+    names.sort_by(|a, b| duration(b).total_cmp(&duration(a)));
+
+    let mut loads = vec![0.0_f64; total];
+    for name in names {
+        let shortest = (0..total).min_by(|&a, &b| loads[a].total_cmp(&loads[b])).unwrap();
+        loads[shortest] += duration(&name);
+        shards[shortest].push(name);
+    }
+
+    shards
+}
+
+/// A CTest `-R` regex that matches exactly `names`.
+pub fn exact_regex(names: &[String]) -> String {
+    let names: Vec<String> = names.iter().map(|name| regex::escape(name)).collect();
+    format!("^({})$", names.join("|"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test(name: &str, labels: &[&str]) -> Test {
+        let json = serde_json::json!({"name": name, "properties": [{"name": "LABELS", "value": labels}]});
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn shares_tests_finds_groups_with_multi_label_tests() {
+        let tests = [test("a", &["fast"]), test("b", &["fast", "net"]), test("c", &["slow"]), test("d", &[])];
+        assert!(shares_tests(&tests, Some("fast")));
+        assert!(shares_tests(&tests, Some("net")));
+        assert!(!shares_tests(&tests, Some("slow")));
+        assert!(!shares_tests(&tests, None));
+    }
+}
@@ -0,0 +1,57 @@
+use std::{fs, path::Path};
+
+/// Why `dir` doesn't look like a build directory that is safe to remove
+/// unasked, None if it does. A directory without a CMakeCache.txt or with
+/// a .git inside is likely a mis-set BUILD_DIR, and one over `max_bytes`
+/// is unexpectedly large. The size walk stops at the limit, so huge
+/// directories are reported as at least that size.
+pub fn suspicion(dir: &str, max_bytes: u64) -> Option<String> {
+    let dir = Path::new(dir);
+    if dir.join(".git").exists() {
+        return Some("it contains a .git directory".to_string());
+    }
+    if !dir.join("CMakeCache.txt").exists() {
+        return Some("it has no CMakeCache.txt, so it may not be a build directory".to_string());
+    }
+
+    let mut size = 0;
+    if !size_within(dir, max_bytes, &mut size) {
+        return Some(format!("it holds at least {}, over the {} limit", human_size(size), human_size(max_bytes)));
+    }
+
+    None
+}
+
+/// Add up the file sizes under `dir` into `size`, without following
+/// symlinks. False once the total goes over `max_bytes`.
+fn size_within(dir: &Path, max_bytes: u64, size: &mut u64) -> bool {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return true;
+    };
+
+    for entry in entries.flatten() {
+        let Ok(meta) = entry.path().symlink_metadata() else {
+            continue;
+        };
+        if meta.is_dir() {
+            if !size_within(&entry.path(), max_bytes, size) {
+                return false;
+            }
+        } else {
+            *size += meta.len();
+            if *size > max_bytes {
+                return false;
+            }
+        }
+    }
+
+    true
+}
+
+fn human_size(bytes: u64) -> String {
+    const GIB: u64 = 1 << 30;
+    match bytes {
+        bytes if bytes >= GIB => format!("{:.1} GiB", bytes as f64 / GIB as f64),
+        bytes => format!("{:.1} MiB", bytes as f64 / (1 << 20) as f64),
+    }
+}
@@ -0,0 +1,52 @@
+use std::{cmp::Reverse, collections::BTreeMap};
+use regex::Regex;
+use serde::Serialize;
+
+/// A GCC/Clang style `file:line:col: level: message [flag]` diagnostic, as
+/// printed by compilers and clang-tidy.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub file: String,
+    pub line: u32,
+    pub column: u32,
+    pub level: String,
+    pub message: String,
+    /// The warning option (`-Wunused-variable`) or clang-tidy check name.
+    pub flag: Option<String>,
+}
+
+pub fn parse(text: &str) -> Vec<Diagnostic> {
+    let ansi = Regex::new(r"\x1b\[[0-9;]*[A-Za-z]").unwrap();
+    let regex = Regex::new(
+        r"^(.+?):(\d+):(\d+): (warning|error|note|fatal error): (.*?)(?: \[([^\]]+)\])?$",
+    )
+    .unwrap();
+
+    text.lines()
+        .filter_map(|line| {
+            let line = ansi.replace_all(line, "");
+            let caps = regex.captures(&line)?;
+            Some(Diagnostic {
+                file: caps[1].to_string(),
+                line: caps[2].parse().ok()?,
+                column: caps[3].parse().ok()?,
+                level: caps[4].to_string(),
+                message: caps[5].to_string(),
+                flag: caps.get(6).map(|flag| flag.as_str().to_string()),
+            })
+        })
+        .collect()
+}
+
+/// Count warnings per flag, most frequent first.
+pub fn count_warnings(diagnostics: &[Diagnostic]) -> Vec<(String, usize)> {
+    let mut counts = BTreeMap::<String, usize>::new();
+    for diag in diagnostics.iter().filter(|diag| diag.level == "warning") {
+        let flag = diag.flag.clone().unwrap_or_else(|| "(no flag)".to_string());
+        *counts.entry(flag).or_default() += 1;
+    }
+
+    let mut counts: Vec<(String, usize)> = counts.into_iter().collect();
+    counts.sort_by_key(|(_, count)| Reverse(*count));
+    counts
+}
@@ -0,0 +1,60 @@
+/// Known failure signatures and what to do about them.
+const HINTS: [(&str, &str); 12] = [
+    (
+        "No CMAKE_CXX_COMPILER could be found",
+        "Install a C++ compiler or point the CXX environment variable at one.",
+    ),
+    (
+        "No CMAKE_C_COMPILER could be found",
+        "Install a C compiler or point the CC environment variable at one.",
+    ),
+    (
+        "unable to find a build program corresponding to \"Ninja\"",
+        "Install Ninja (e.g. `apt install ninja-build`) and make sure it is on PATH.",
+    ),
+    (
+        "ninja: not found",
+        "Install Ninja (e.g. `apt install ninja-build`) and make sure it is on PATH.",
+    ),
+    ("cmake: not found", "Install CMake 3.25+ and make sure it is on PATH."),
+    ("ctest: not found", "CTest ships with CMake, install CMake and make sure it is on PATH."),
+    (
+        "clang-tidy: not found",
+        "Install clang-tidy, or name a versioned binary with --clang-tidy-bin.",
+    ),
+    (
+        "compile_commands.json not found",
+        "Configure with -DCMAKE_EXPORT_COMPILE_COMMANDS=ON so the compile database is written.",
+    ),
+    (
+        "Could not auto-detect compilation database",
+        "Configure with -DCMAKE_EXPORT_COMPILE_COMMANDS=ON so the compile database is written.",
+    ),
+    (
+        "does not match the generator used previously",
+        "The build directory was made with another generator, rerun with --destroy.",
+    ),
+    (
+        "does not appear to contain CMakeLists.txt",
+        "Run from the project root, configure uses the current directory as the source.",
+    ),
+    (
+        "Could not find a package configuration file provided by",
+        "Install the missing package or add its install prefix with --prefix-path.",
+    ),
+];
+
+/// Print a hint for every known signature found in a failed step's output.
+pub fn explain(output: &str) {
+    let mut printed = Vec::new();
+    for (signature, hint) in HINTS {
+        if output.contains(signature) && !printed.contains(&hint) {
+            eprintln!("Hint: {hint}");
+            printed.push(hint);
+        }
+    }
+
+    if printed.is_empty() {
+        eprintln!("Hint: no known cause found for this failure");
+    }
+}
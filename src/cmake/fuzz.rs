@@ -0,0 +1,60 @@
+use std::{env, fs, path::{Path, PathBuf}, process::Command};
+use glob::glob;
+use super::read_cache_var;
+
+/// libFuzzer needs clang, GCC has no -fsanitize=fuzzer. Checked before the
+/// configure, as GCC already fails CMake's compiler probe on the fuzzer
+/// flags. The compiler is the one the configure will use: `profile_cxx`
+/// from a toolchain profile, else the cached one, else `$CXX`, else `c++`.
+pub fn check_compiler(artifacts: &str, profile_cxx: Option<&str>) -> bool {
+    let compiler = profile_cxx
+        .map(str::to_string)
+        .or_else(|| read_cache_var(artifacts, "CMAKE_CXX_COMPILER"))
+        .or_else(|| env::var("CXX").ok())
+        .unwrap_or_else(|| "c++".to_string());
+    let named_clang = Path::new(&compiler)
+        .file_name()
+        .is_some_and(|name| name.to_string_lossy().contains("clang"));
+    // A plain c++ may well be clang, e.g. on macOS.
+    let is_clang = named_clang
+        || Command::new(&compiler)
+            .arg("--version")
+            .output()
+            .is_ok_and(|output| String::from_utf8_lossy(&output.stdout).contains("clang"));
+
+    if !is_clang {
+        eprintln!("--fuzz needs clang, not {compiler}. Configure a fresh build directory with CC=clang CXX=clang++");
+    }
+
+    is_clang
+}
+
+/// Run the libFuzzer binary of `target` on `corpus` for `seconds`, writing
+/// any crashing, leaking or timing out inputs to `crash_dir`.
+pub fn run(artifacts: &str, target: &str, corpus: &str, seconds: u64, crash_dir: &str) -> bool {
+    let Some(binary) = find_binary(artifacts, target) else {
+        eprintln!("No '{target}' executable found under {artifacts}");
+        return false;
+    };
+
+    fs::create_dir_all(corpus).unwrap_or_else(|_| panic!("Failed to create {corpus}"));
+    fs::create_dir_all(crash_dir).unwrap_or_else(|_| panic!("Failed to create {crash_dir}"));
+
+    let status = Command::new(&binary)
+        .arg(corpus)
+        .arg(format!("-max_total_time={seconds}"))
+        // libFuzzer prepends the prefix as is, the trailing slash makes it a directory.
+        .arg(format!("-artifact_prefix={crash_dir}/"))
+        .status()
+        .expect("failed to execute process");
+
+    let crashes = fs::read_dir(crash_dir).map(|entries| entries.count()).unwrap_or_default();
+    println!("Fuzzed {target} for up to {seconds}s, {crashes} crashing inputs in {crash_dir}");
+
+    status.success()
+}
+
+fn find_binary(artifacts: &str, target: &str) -> Option<PathBuf> {
+    let pattern = format!("{artifacts}/**/{target}{}", env::consts::EXE_SUFFIX);
+    glob(&pattern).ok()?.flatten().find(|path| path.is_file())
+}
@@ -0,0 +1,82 @@
+use std::thread;
+
+/// How many build jobs to run.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Jobs {
+    Count(u32),
+    /// Size to the CPUs this process may actually use, see `auto_count`.
+    Auto,
+}
+
+/// Parse `--jobs`, either a count or `auto`.
+pub fn parse(text: &str) -> Result<Jobs, String> {
+    if text == "auto" {
+        return Ok(Jobs::Auto);
+    }
+
+    match text.parse() {
+        Ok(0) | Err(_) => Err(format!("expected a job count above 0 or auto, not '{text}'")),
+        Ok(count) => Ok(Jobs::Count(count)),
+    }
+}
+
+impl Jobs {
+    pub fn count(self) -> u32 {
+        match self {
+            Jobs::Count(count) => count,
+            Jobs::Auto => auto_count(),
+        }
+    }
+}
+
+/// The CPUs available to this process: the CPU affinity mask, capped by a
+/// cgroup CPU quota on Linux, rounded up to whole CPUs. Elsewhere, or with
+/// no quota, just the CPUs the OS reports.
+pub fn auto_count() -> u32 {
+    let cpus = thread::available_parallelism().map_or(1, |count| count.get() as u32);
+    match cgroup_quota() {
+        Some(quota) => quota.min(cpus),
+        None => cpus,
+    }
+}
+
+/// The CPU quota of the cgroup this process runs in, None when unlimited.
+/// cgroup v2 keeps it as "QUOTA PERIOD" (or "max PERIOD") in cpu.max,
+/// cgroup v1 splits it over cpu.cfs_quota_us (-1 when unlimited) and
+/// cpu.cfs_period_us.
+#[cfg(target_os = "linux")]
+fn cgroup_quota() -> Option<u32> {
+    let (quota, period) = cgroup_v2_limit().or_else(cgroup_v1_limit)?;
+    (quota > 0 && period > 0).then(|| quota.div_ceil(period).max(1) as u32)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn cgroup_quota() -> Option<u32> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn cgroup_v2_limit() -> Option<(u64, u64)> {
+    // A "0::PATH" line means cgroup v2. Inside a container PATH is usually
+    // "/", outside it the quota may sit on the process's own cgroup.
+    let cgroups = std::fs::read_to_string("/proc/self/cgroup").ok()?;
+    let path = cgroups.lines().find_map(|line| line.strip_prefix("0::"))?;
+    let candidates = [format!("/sys/fs/cgroup{path}/cpu.max"), "/sys/fs/cgroup/cpu.max".to_string()];
+
+    candidates.iter().find_map(|file| {
+        let text = std::fs::read_to_string(file).ok()?;
+        let (quota, period) = text.trim().split_once(' ')?;
+        Some((quota.parse().ok()?, period.parse().ok()?))
+    })
+}
+
+#[cfg(target_os = "linux")]
+fn cgroup_v1_limit() -> Option<(u64, u64)> {
+    ["/sys/fs/cgroup/cpu", "/sys/fs/cgroup/cpu,cpuacct"].iter().find_map(|dir| {
+        let read = |name: &str| std::fs::read_to_string(format!("{dir}/{name}")).ok();
+        // An unlimited quota is -1, which fails to parse and reads as None.
+        let quota = read("cpu.cfs_quota_us")?.trim().parse().ok()?;
+        let period = read("cpu.cfs_period_us")?.trim().parse().ok()?;
+        Some((quota, period))
+    })
+}
@@ -0,0 +1,132 @@
+use std::fs::read_to_string;
+use serde::Deserialize;
+
+/// A single `<testcase>` from a CTest JUnit report.
+#[derive(Deserialize, Debug)]
+pub struct TestCase {
+    #[serde(rename = "@name")]
+    pub name: String,
+
+    #[serde(rename = "@time", default)]
+    time: Option<String>,
+
+    #[serde(rename = "@status", default)]
+    status: Option<String>,
+
+    #[serde(default)]
+    failure: Option<Message>,
+
+    #[serde(default)]
+    error: Option<Message>,
+
+    #[serde(default)]
+    skipped: Option<Message>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Message {
+    #[serde(rename = "@message", default)]
+    message: Option<String>,
+}
+
+/// The `<testsuite>` CTest writes for `--output-junit`.
+#[derive(Deserialize, Debug)]
+pub struct Report {
+    #[serde(rename = "@name", default)]
+    pub name: Option<String>,
+
+    #[serde(rename = "testcase", default)]
+    pub cases: Vec<TestCase>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    Passed,
+    Failed,
+    Skipped,
+}
+
+impl TestCase {
+    pub fn outcome(&self) -> Outcome {
+        if self.failure.is_some() || self.error.is_some() {
+            return Outcome::Failed;
+        }
+
+        match self.status.as_deref() {
+            Some("fail") => Outcome::Failed,
+            Some("notrun") | Some("disabled") => Outcome::Skipped,
+            _ if self.skipped.is_some() => Outcome::Skipped,
+            _ => Outcome::Passed,
+        }
+    }
+
+    /// Duration in seconds, if the report recorded one.
+    pub fn time(&self) -> Option<f64> {
+        self.time.as_deref().and_then(|time| time.parse().ok())
+    }
+
+    /// The failure or skip message attached to the case, if any.
+    pub fn message(&self) -> Option<&str> {
+        [&self.failure, &self.error, &self.skipped]
+            .into_iter()
+            .flatten()
+            .find_map(|msg| msg.message.as_deref())
+    }
+}
+
+pub fn read_report(path: &str) -> Result<Report, String> {
+    let xml = read_to_string(path).map_err(|e| format!("Failed to read {path}: {e}"))?;
+    quick_xml::de::from_str(&xml).map_err(|e| format!("Failed to parse {path}: {e}"))
+}
+
+/// Render the report as TAP version 13.
+pub fn to_tap(report: &Report) -> String {
+    let mut tap = format!("TAP version 13\n1..{}\n", report.cases.len());
+    for (idx, case) in report.cases.iter().enumerate() {
+        let num = idx + 1;
+        match case.outcome() {
+            Outcome::Passed => tap.push_str(&format!("ok {num} - {}\n", case.name)),
+            Outcome::Skipped => tap.push_str(&format!(
+                "ok {num} - {} # SKIP {}\n",
+                case.name,
+                case.message().unwrap_or_default()
+            )),
+            Outcome::Failed => {
+                tap.push_str(&format!("not ok {num} - {}\n", case.name));
+                if let Some(msg) = case.message() {
+                    tap.push_str(&format!("  ---\n  message: {msg:?}\n  ...\n"));
+                }
+            }
+        }
+    }
+
+    tap
+}
+
+/// Render the report as a table of counts followed by the failed tests.
+pub fn to_table(report: &Report) -> String {
+    let count = |outcome: Outcome| report.cases.iter().filter(|case| case.outcome() == outcome).count();
+
+    let mut table = format!("{:<30} {:>6} {:>6} {:>6} {:>7}\n", "Suite", "Total", "Passed", "Failed", "Skipped");
+    table.push_str(&format!(
+        "{:<30} {:>6} {:>6} {:>6} {:>7}\n",
+        report.name.as_deref().unwrap_or("(unnamed)"),
+        report.cases.len(),
+        count(Outcome::Passed),
+        count(Outcome::Failed),
+        count(Outcome::Skipped),
+    ));
+
+    let failed: Vec<&TestCase> = report.cases.iter().filter(|case| case.outcome() == Outcome::Failed).collect();
+    if !failed.is_empty() {
+        table.push_str("\nFailed tests:\n");
+        for case in failed {
+            match case.message() {
+                Some(msg) => table.push_str(&format!("  {}: {msg}\n", case.name)),
+                None => table.push_str(&format!("  {}\n", case.name)),
+            }
+        }
+    }
+
+    table
+}
@@ -0,0 +1,92 @@
+use std::path::Path;
+
+/// What kind of filesystem a path lives on.
+#[derive(Debug, PartialEq)]
+pub enum FsKind {
+    Local,
+    /// A network filesystem, by name.
+    Network(String),
+    /// FUSE, which may be local (fuse-overlayfs in rootless containers) or
+    /// remote (sshfs), by name.
+    Fuse(String),
+}
+
+/// The kind of filesystem `path` lives on. Paths that don't exist yet are
+/// checked through their closest existing ancestor. Errors when the
+/// filesystem can't be determined.
+pub fn fs_kind(path: &str) -> Result<FsKind, String> {
+    let existing = Path::new(path)
+        .ancestors()
+        .find(|ancestor| ancestor.exists())
+        .ok_or_else(|| format!("{path}: no existing parent directory"))?;
+
+    fs_type(existing)
+}
+
+#[cfg(target_os = "linux")]
+fn fs_type(path: &Path) -> Result<FsKind, String> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    // f_type magic numbers from statfs(2).
+    const FUSE: u32 = 0x65735546;
+    const NETWORK_FS: [(u32, &str); 7] = [
+        (0x6969, "nfs"),
+        (0x517b, "smb"),
+        (0xff534d42, "cifs"),
+        (0xfe534d42, "smb2"),
+        (0x5346414f, "afs"),
+        (0x01021997, "9p"),
+        (0x00c36400, "ceph"),
+    ];
+
+    let c_path = CString::new(path.as_os_str().as_bytes()).map_err(|e| e.to_string())?;
+    let mut stat = std::mem::MaybeUninit::<libc::statfs>::zeroed();
+    // SAFETY: c_path is NUL terminated and statfs only writes to `stat`.
+    if unsafe { libc::statfs(c_path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+        return Err(format!("statfs {}: {}", path.display(), std::io::Error::last_os_error()));
+    }
+
+    // SAFETY: statfs succeeded, so it filled in the struct. f_type's integer
+    // type differs between architectures, the magic numbers all fit in 32 bits.
+    let f_type = unsafe { stat.assume_init() }.f_type as u32;
+    if f_type == FUSE {
+        return Ok(FsKind::Fuse("fuse".to_string()));
+    }
+    Ok(match NETWORK_FS.iter().find(|(magic, _)| *magic == f_type) {
+        Some((_, name)) => FsKind::Network(name.to_string()),
+        None => FsKind::Local,
+    })
+}
+
+#[cfg(target_os = "macos")]
+fn fs_type(path: &Path) -> Result<FsKind, String> {
+    use std::{
+        ffi::{CStr, CString},
+        os::unix::ffi::OsStrExt,
+    };
+
+    const NETWORK_FS: [&str; 4] = ["nfs", "smbfs", "afpfs", "webdav"];
+    const FUSE: [&str; 2] = ["osxfuse", "macfuse"];
+
+    let c_path = CString::new(path.as_os_str().as_bytes()).map_err(|e| e.to_string())?;
+    let mut stat = std::mem::MaybeUninit::<libc::statfs>::zeroed();
+    // SAFETY: c_path is NUL terminated and statfs only writes to `stat`.
+    if unsafe { libc::statfs(c_path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+        return Err(format!("statfs {}: {}", path.display(), std::io::Error::last_os_error()));
+    }
+
+    // SAFETY: statfs succeeded, so it filled in the struct and its NUL
+    // terminated type name.
+    let stat = unsafe { stat.assume_init() };
+    let name = unsafe { CStr::from_ptr(stat.f_fstypename.as_ptr()) }.to_string_lossy().into_owned();
+    Ok(match name.as_str() {
+        _ if NETWORK_FS.contains(&name.as_str()) => FsKind::Network(name),
+        _ if FUSE.contains(&name.as_str()) => FsKind::Fuse(name),
+        _ => FsKind::Local,
+    })
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn fs_type(_path: &Path) -> Result<FsKind, String> {
+    Err("checking the filesystem type isn't supported on this platform".to_string())
+}
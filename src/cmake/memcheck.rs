@@ -0,0 +1,61 @@
+use std::fs;
+use glob::glob;
+use regex::Regex;
+
+/// CTest `--overwrite` arguments that make `-T memcheck` run the tests under
+/// `wrapper`, e.g. `valgrind --leak-check=full`. The wrapper is split on
+/// whitespace, shell quoting is not supported.
+pub fn overwrite_args(wrapper: &str) -> Vec<String> {
+    let mut words = wrapper.split_whitespace();
+    let command = words.next().unwrap_or_default();
+    let options: Vec<&str> = words.collect();
+
+    vec![
+        "--overwrite".to_string(),
+        format!("MemoryCheckCommand={command}"),
+        "--overwrite".to_string(),
+        format!("MemoryCheckCommandOptions={}", options.join(" ")),
+    ]
+}
+
+/// Copy the per test MemoryChecker logs CTest leaves under Testing/Temporary
+/// into `dest`, returning how many were copied.
+pub fn collect_logs(artifacts: &str, dest: &str) -> usize {
+    let pattern = format!("{artifacts}/Testing/Temporary/MemoryChecker.*.log");
+    fs::create_dir_all(dest).unwrap();
+
+    let mut count = 0;
+    for log in glob(&pattern).expect("Failed to read glob pattern").flatten() {
+        let Some(name) = log.file_name() else { continue };
+        match fs::copy(&log, format!("{dest}/{}", name.to_string_lossy())) {
+            Ok(_) => count += 1,
+            Err(e) => eprintln!("Failed to copy {}: {e}", log.display()),
+        }
+    }
+
+    count
+}
+
+/// Total defects per type in the DynamicAnalysis.xml of the latest
+/// `ctest -T memcheck` run, whose directory Testing/TAG names.
+pub fn defect_counts(artifacts: &str) -> Result<Vec<(String, u64)>, String> {
+    let tag_file = format!("{artifacts}/Testing/TAG");
+    let tag = fs::read_to_string(&tag_file).map_err(|e| format!("Failed to read {tag_file}: {e}"))?;
+    let tag = tag.lines().next().unwrap_or_default();
+
+    let xml_path = format!("{artifacts}/Testing/{tag}/DynamicAnalysis.xml");
+    let xml = fs::read_to_string(&xml_path).map_err(|e| format!("Failed to read {xml_path}: {e}"))?;
+
+    // Each test lists its counts as <Defect type="Memory Leak">N</Defect>.
+    let regex = Regex::new(r#"<Defect type="([^"]+)">(\d+)</Defect>"#).unwrap();
+    let mut counts: Vec<(String, u64)> = Vec::new();
+    for caps in regex.captures_iter(&xml) {
+        let count: u64 = caps[2].parse().unwrap_or_default();
+        match counts.iter_mut().find(|(kind, _)| *kind == caps[1]) {
+            Some((_, total)) => *total += count,
+            None => counts.push((caps[1].to_string(), count)),
+        }
+    }
+
+    Ok(counts)
+}
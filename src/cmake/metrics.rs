@@ -0,0 +1,62 @@
+use std::{fs, time::Duration};
+use serde::Serialize;
+use super::summary::Steps;
+
+#[derive(Serialize, Debug)]
+struct StepMetrics<'a> {
+    name: &'a str,
+    seconds: f64,
+}
+
+/// Build metrics for dashboards, kept apart from the pass/fail summary.
+#[derive(Serialize, Debug)]
+struct Metrics<'a> {
+    total_seconds: f64,
+    steps: Vec<StepMetrics<'a>>,
+
+    /// Object files Ninja compiled, None for other generators or without a
+    /// build.
+    files_compiled: Option<usize>,
+
+    /// Largest resident set of any finished child process, e.g. the
+    /// heaviest compiler or linker run.
+    peak_child_rss_kb: Option<u64>,
+}
+
+pub fn write(path: &str, steps: &Steps, total: Duration, files_compiled: Option<usize>) {
+    let metrics = Metrics {
+        total_seconds: total.as_secs_f64(),
+        steps: steps
+            .results
+            .iter()
+            .map(|result| StepMetrics { name: &result.name, seconds: result.duration.as_secs_f64() })
+            .collect(),
+        files_compiled,
+        peak_child_rss_kb: peak_child_rss_kb(),
+    };
+
+    let json = serde_json::to_string_pretty(&metrics).unwrap();
+    fs::write(path, json).unwrap_or_else(|_| panic!("Failed to create {path}"));
+}
+
+#[cfg(unix)]
+fn peak_child_rss_kb() -> Option<u64> {
+    let mut usage = std::mem::MaybeUninit::<libc::rusage>::zeroed();
+    // SAFETY: getrusage only writes to the struct it is given.
+    if unsafe { libc::getrusage(libc::RUSAGE_CHILDREN, usage.as_mut_ptr()) } != 0 {
+        return None;
+    }
+
+    // SAFETY: getrusage succeeded, so it filled in the struct.
+    let max_rss = unsafe { usage.assume_init() }.ru_maxrss as u64;
+    // Linux reports kilobytes, macOS bytes.
+    match cfg!(target_os = "macos") {
+        true => Some(max_rss / 1024),
+        false => Some(max_rss),
+    }
+}
+
+#[cfg(not(unix))]
+fn peak_child_rss_kb() -> Option<u64> {
+    None
+}
@@ -0,0 +1,71 @@
+use std::{fs, path::Path};
+use regex::Regex;
+
+/// One output of `ninja -t deps`, with the inputs Ninja recorded for it.
+#[derive(Debug)]
+pub struct Deps {
+    pub output: String,
+    /// VALID, STALE or MISSING, as Ninja prints it.
+    pub state: String,
+    pub inputs: Vec<String>,
+}
+
+/// Parse `ninja -t deps` output, which lists each output as
+///
+/// ```text
+/// CMakeFiles/app.dir/main.cpp.o: #deps 2, deps mtime 1700000000 (VALID)
+///     ../main.cpp
+///     ../main.h
+/// ```
+pub fn parse(text: &str) -> Vec<Deps> {
+    let mut deps: Vec<Deps> = Vec::new();
+    for line in text.lines() {
+        if let Some(input) = line.strip_prefix("    ") {
+            if let Some(current) = deps.last_mut() {
+                current.inputs.push(input.to_string());
+            }
+        } else if let Some((output, info)) = line.split_once(": #deps") {
+            let state = info
+                .rsplit_once('(')
+                .map(|(_, state)| state.trim_end_matches(')').to_string())
+                .unwrap_or_default();
+            deps.push(Deps { output: output.to_string(), state, inputs: Vec::new() });
+        }
+    }
+
+    deps
+}
+
+/// Whether `output` is an object file CMake builds for `target`.
+pub fn belongs_to(output: &str, target: &str) -> bool {
+    output.contains(&format!("CMakeFiles/{target}.dir/"))
+}
+
+/// The CMake target an object file belongs to, from its
+/// `CMakeFiles/<target>.dir/` component.
+pub fn target_of(output: &str) -> Option<&str> {
+    let (_, rest) = output.rsplit_once("CMakeFiles/")?;
+    rest.split_once(".dir/").map(|(target, _)| target)
+}
+
+/// The quoted `#include "..."` directives of the source `dep` was compiled
+/// from that none of its recorded inputs match. Angle bracket includes are
+/// left out, those are mostly system headers behind platform conditionals
+/// and would drown the real misses. The source is the first recorded input,
+/// relative to `build_dir`.
+pub fn untracked_includes(dep: &Deps, build_dir: &str) -> Vec<String> {
+    let Some(source) = dep.inputs.first() else {
+        return Vec::new();
+    };
+    let Ok(text) = fs::read_to_string(Path::new(build_dir).join(source)) else {
+        return Vec::new();
+    };
+
+    let include = Regex::new(r#"^\s*#\s*include\s*"([^"]+)""#).unwrap();
+    text.lines()
+        .filter_map(|line| include.captures(line).map(|caps| caps[1].to_string()))
+        .filter(|header| {
+            !dep.inputs.iter().any(|input| input == header || input.ends_with(&format!("/{header}")))
+        })
+        .collect()
+}
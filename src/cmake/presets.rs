@@ -0,0 +1,142 @@
+use std::fs::read_to_string;
+use serde::Deserialize;
+use serde_json::Value;
+
+const PRESET_FILES: [&str; 2] = ["CMakePresets.json", "CMakeUserPresets.json"];
+
+#[derive(Deserialize, Debug, Default)]
+struct Presets {
+    #[serde(rename = "configurePresets", default)]
+    configure: Vec<ConfigurePreset>,
+
+    #[serde(rename = "buildPresets", default)]
+    build: Vec<BuildPreset>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ConfigurePreset {
+    name: String,
+
+    #[serde(default)]
+    generator: Option<String>,
+
+    /// Either a single preset name or a list of them.
+    #[serde(default)]
+    inherits: Value,
+
+    #[serde(rename = "cacheVariables", default)]
+    cache_variables: serde_json::Map<String, Value>,
+}
+
+#[derive(Deserialize, Debug)]
+struct BuildPreset {
+    name: String,
+
+    #[serde(rename = "configurePreset", default)]
+    configure_preset: Option<String>,
+
+    #[serde(default)]
+    configuration: Option<String>,
+}
+
+impl Presets {
+    fn load(source_dir: &str) -> Result<Presets, String> {
+        let mut presets = Presets::default();
+        for file in PRESET_FILES {
+            let path = format!("{source_dir}/{file}");
+            let Ok(text) = read_to_string(&path) else {
+                continue;
+            };
+
+            let parsed: Presets =
+                serde_json::from_str(&text).map_err(|e| format!("Failed to parse {path}: {e}"))?;
+            presets.configure.extend(parsed.configure);
+            presets.build.extend(parsed.build);
+        }
+
+        Ok(presets)
+    }
+
+    fn configure_preset(&self, name: &str) -> Option<&ConfigurePreset> {
+        self.configure.iter().find(|preset| preset.name == name)
+    }
+
+    /// Walk `name` and the presets it inherits from, depth first, returning
+    /// the first value `field` yields.
+    fn resolve<T>(&self, name: &str, field: &dyn Fn(&ConfigurePreset) -> Option<T>) -> Option<T> {
+        let preset = self.configure_preset(name)?;
+        if let Some(value) = field(preset) {
+            return Some(value);
+        }
+
+        let parents: Vec<&str> = match &preset.inherits {
+            Value::String(parent) => vec![parent.as_str()],
+            Value::Array(parents) => parents.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        parents.into_iter().find_map(|parent| self.resolve(parent, field))
+    }
+}
+
+/// A cache variable is either `"VALUE"` or `{ "type": ..., "value": "VALUE" }`.
+fn cache_value(value: &Value) -> Option<String> {
+    match value {
+        Value::String(value) => Some(value.clone()),
+        Value::Bool(value) => Some(if *value { "ON" } else { "OFF" }.to_string()),
+        Value::Object(obj) => obj.get("value").and_then(cache_value),
+        _ => None,
+    }
+}
+
+fn is_multi_config(generator: &str) -> bool {
+    generator == "Ninja Multi-Config"
+        || generator.starts_with("Visual Studio")
+        || generator == "Xcode"
+}
+
+/// Find the build preset tied to `configure`, checking that `config` can be
+/// built by it.
+pub fn build_preset(source_dir: &str, configure: &str, config: Option<&str>) -> Result<String, String> {
+    let presets = Presets::load(source_dir)?;
+    if presets.configure_preset(configure).is_none() {
+        let names: Vec<&str> = presets.configure.iter().map(|preset| preset.name.as_str()).collect();
+        return Err(format!(
+            "Configure preset '{configure}' not found, available: {}",
+            names.join(", ")
+        ));
+    }
+
+    if let Some(config) = config {
+        let generator = presets
+            .resolve(configure, &|preset| preset.generator.clone())
+            .unwrap_or_default();
+        if !is_multi_config(&generator) {
+            return Err(format!(
+                "--config needs a multi-config generator, preset '{configure}' uses '{generator}'"
+            ));
+        }
+
+        let types = presets.resolve(configure, &|preset| {
+            preset.cache_variables.get("CMAKE_CONFIGURATION_TYPES").and_then(cache_value)
+        });
+        if let Some(types) = types {
+            if !types.split(';').any(|ty| ty == config) {
+                return Err(format!("'{config}' is not one of the preset's configurations: {types}"));
+            }
+        }
+    }
+
+    let candidates: Vec<&BuildPreset> = presets
+        .build
+        .iter()
+        .filter(|preset| preset.configure_preset.as_deref() == Some(configure))
+        .collect();
+
+    // Prefer a build preset dedicated to the requested configuration.
+    candidates
+        .iter()
+        .find(|preset| config.is_some() && preset.configuration.as_deref() == config)
+        .or(candidates.first())
+        .map(|preset| preset.name.clone())
+        .ok_or_else(|| format!("No build preset uses configure preset '{configure}'"))
+}
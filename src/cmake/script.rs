@@ -0,0 +1,71 @@
+use std::{ffi::OsStr, fs, process::Command};
+
+/// A POSIX shell script of the commands a run would execute.
+pub struct Script {
+    text: String,
+}
+
+impl Script {
+    pub fn new() -> Script {
+        Script { text: "#!/bin/sh\n# Generated by cli_assist cmake --emit-script\nset -e\n".to_string() }
+    }
+
+    /// Add `cmd` as the `step` step.
+    pub fn command(&mut self, step: &str, cmd: &Command) {
+        self.line(step, &render(cmd));
+    }
+
+    /// Add a shell line as the `step` step.
+    pub fn line(&mut self, step: &str, line: &str) {
+        self.text.push_str(&format!("\n# {step}\n{line}\n"));
+    }
+
+    /// Note a step that only cli_assist itself can run.
+    pub fn skipped(&mut self, step: &str) {
+        self.text.push_str(&format!("\n# {step}: not emitted, only cli_assist runs this step\n"));
+    }
+
+    /// Write the script to `path`, executable on Unix.
+    pub fn write(&self, path: &str) -> Result<(), String> {
+        fs::write(path, &self.text).map_err(|e| format!("Failed to write {path}: {e}"))?;
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(path, fs::Permissions::from_mode(0o755))
+                .map_err(|e| format!("Failed to make {path} executable: {e}"))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// `cmd` as a shell command line, prefixed with the environment it sets.
+pub fn render(cmd: &Command) -> String {
+    let mut words = Vec::new();
+    let mut removed = Vec::new();
+    for (key, value) in cmd.get_envs() {
+        match value {
+            Some(value) => words.push(format!("{}={}", key.to_string_lossy(), quote(value))),
+            None => removed.push(format!("-u {}", quote(key))),
+        }
+    }
+    if !removed.is_empty() {
+        words.insert(0, format!("env {}", removed.join(" ")));
+    }
+    words.push(quote(cmd.get_program()));
+    words.extend(cmd.get_args().map(quote));
+
+    words.join(" ")
+}
+
+/// Quote `word` for a POSIX shell, leaving plain words as they are.
+pub fn quote(word: impl AsRef<OsStr>) -> String {
+    let word = word.as_ref().to_string_lossy();
+    let plain = |c: char| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c);
+    if !word.is_empty() && word.chars().all(plain) {
+        return word.into_owned();
+    }
+
+    format!("'{}'", word.replace('\'', r"'\''"))
+}
@@ -0,0 +1,56 @@
+use std::{fs::{self, File}, io::Read, process::Command};
+
+const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+
+/// Move the debug info of every installed ELF binary listed in the build's
+/// install_manifest.txt into a `<binary>.debug` file next to it, and leave a
+/// .gnu_debuglink in the stripped binary so debuggers can find it.
+pub fn split(artifacts: &str, objcopy: &str) -> bool {
+    let manifest_path = format!("{artifacts}/install_manifest.txt");
+    let manifest = match fs::read_to_string(&manifest_path) {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            eprintln!("Failed to read {manifest_path}: {e}");
+            return false;
+        }
+    };
+
+    let mut success = true;
+    let mut count = 0;
+    for binary in manifest.lines().filter(|line| is_elf(line)) {
+        let debug_file = format!("{binary}.debug");
+        let split = run(Command::new(objcopy).arg("--only-keep-debug").arg(binary).arg(&debug_file))
+            && run(Command::new(objcopy)
+                .arg("--strip-debug")
+                .arg(format!("--add-gnu-debuglink={debug_file}"))
+                .arg(binary));
+
+        if split {
+            println!("Wrote {debug_file}");
+            count += 1;
+        } else {
+            eprintln!("Failed to split debug info from {binary}");
+            success = false;
+        }
+    }
+
+    println!("Split debug info from {count} binaries");
+    success
+}
+
+fn is_elf(path: &str) -> bool {
+    let mut magic = [0; 4];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .is_ok_and(|_| magic == ELF_MAGIC)
+}
+
+fn run(cmd: &mut Command) -> bool {
+    match cmd.status() {
+        Ok(status) => status.success(),
+        Err(e) => {
+            eprintln!("{}: {e}", cmd.get_program().to_string_lossy());
+            false
+        }
+    }
+}
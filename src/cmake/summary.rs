@@ -0,0 +1,179 @@
+use std::{collections::BTreeMap, fs, path::Path, time::{Duration, Instant}};
+use glob::glob;
+use regex::Regex;
+use serde::Serialize;
+use super::junit::{self, Outcome};
+use crate::config::Retry;
+
+#[derive(Serialize, Debug)]
+pub struct StepResult {
+    pub name: String,
+    pub success: bool,
+
+    /// Wall time including retries, reported by --metrics-json only.
+    #[serde(skip)]
+    pub duration: Duration,
+}
+
+/// The steps run so far, in order, and whether the run is still passing.
+#[derive(Debug)]
+pub struct Steps {
+    pub status: bool,
+    pub results: Vec<StepResult>,
+
+    /// Retry policy per step name, steps not listed aren't retried. A step
+    /// named `install:/opt/app` uses the policy of `install`.
+    pub retries: BTreeMap<String, Retry>,
+}
+
+impl Steps {
+    pub fn new() -> Steps {
+        Steps { status: true, results: Vec::new(), retries: BTreeMap::new() }
+    }
+
+    /// Run `step` if it's enabled and no earlier step has failed.
+    pub fn run(&mut self, name: &str, enabled: bool, step: impl FnMut() -> bool) {
+        if !self.status || !enabled {
+            return;
+        }
+
+        let policy = name.split_once(':').map_or(name, |(step, _)| step);
+        let retry = self.retries.get(policy).copied().unwrap_or_default();
+        let start = Instant::now();
+        let success = retry.run(name, step);
+        self.results.push(StepResult { name: name.to_string(), success, duration: start.elapsed() });
+        self.status = success;
+    }
+
+    pub fn ran(&self, name: &str) -> bool {
+        self.results.iter().any(|result| result.name == name)
+    }
+}
+
+/// Test counts parsed from the JUnit report.
+#[derive(Serialize, Debug, Default)]
+pub struct TestSummary {
+    total: usize,
+    passed: usize,
+    failed: usize,
+    skipped: usize,
+    failures: Vec<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parse_error: Option<String>,
+}
+
+impl TestSummary {
+    /// Summarise the report at `path`, recording why if it can't be read.
+    pub fn from_report(path: &str) -> TestSummary {
+        let report = match junit::read_report(path) {
+            Ok(report) => report,
+            Err(e) => return TestSummary { parse_error: Some(e), ..Default::default() },
+        };
+
+        let mut summary = TestSummary { total: report.cases.len(), ..Default::default() };
+        for case in &report.cases {
+            match case.outcome() {
+                Outcome::Passed => summary.passed += 1,
+                Outcome::Skipped => summary.skipped += 1,
+                Outcome::Failed => {
+                    summary.failed += 1;
+                    summary.failures.push(case.name.clone());
+                }
+            }
+        }
+
+        summary
+    }
+}
+
+/// Line coverage totals from the Coverage.xml CTest writes under Testing/.
+#[derive(Serialize, Debug)]
+pub struct CoverageSummary {
+    loc_tested: u64,
+    loc_untested: u64,
+    percent: f64,
+}
+
+impl CoverageSummary {
+    fn from_xml(xml: &str) -> Option<CoverageSummary> {
+        // Per file values come first, the last of each tag is the total.
+        let last = |tag: &str| {
+            let regex = Regex::new(&format!("<{tag}>([0-9.]+)</{tag}>")).unwrap();
+            regex.captures_iter(xml).last().map(|caps| caps[1].to_string())
+        };
+
+        Some(CoverageSummary {
+            loc_tested: last("LOCTested")?.parse().ok()?,
+            loc_untested: last("LOCUntested")?.parse().ok()?,
+            percent: last("PercentCoverage")?.parse().ok()?,
+        })
+    }
+}
+
+/// The latest Coverage.xml CTest wrote into the build directory.
+fn latest_coverage_xml(artifacts: &str) -> Option<String> {
+    glob(&format!("{artifacts}/Testing/*/Coverage.xml"))
+        .ok()?
+        .filter_map(Result::ok)
+        .max_by_key(|path| fs::metadata(path).and_then(|meta| meta.modified()).ok())
+        .map(|path| path.to_string_lossy().into_owned())
+}
+
+#[derive(Serialize)]
+struct CombinedReport {
+    junit: String,
+    coverage: Option<String>,
+    tests: TestSummary,
+    coverage_summary: Option<CoverageSummary>,
+}
+
+/// Gather the JUnit report and coverage results into `report_dir`:
+///
+/// - `<junit>`: the JUnit report, already written there by the test step
+/// - `coverage.xml`: a copy of CTest's Coverage.xml
+/// - `summary.json`: names both files with their headline numbers
+pub fn write_combined_report(report_dir: &str, junit_file: &str, artifacts: &str) -> Result<(), String> {
+    let coverage_xml = latest_coverage_xml(artifacts);
+    let coverage_copy = format!("{report_dir}/coverage.xml");
+    if let Some(ref xml) = coverage_xml {
+        fs::copy(xml, &coverage_copy).map_err(|e| format!("Failed to copy {xml}: {e}"))?;
+    } else {
+        eprintln!("No Coverage.xml found under {artifacts}/Testing");
+    }
+
+    let file_name = |path: &str| Path::new(path).file_name().unwrap_or_default().to_string_lossy().into_owned();
+    let report = CombinedReport {
+        junit: file_name(junit_file),
+        coverage: coverage_xml.as_ref().map(|_| file_name(&coverage_copy)),
+        tests: TestSummary::from_report(junit_file),
+        coverage_summary: coverage_xml
+            .and_then(|xml| fs::read_to_string(xml).ok())
+            .and_then(|xml| CoverageSummary::from_xml(&xml)),
+    };
+
+    let json = serde_json::to_string_pretty(&report).map_err(|e| format!("Failed to serialize summary.json: {e}"))?;
+    fs::write(format!("{report_dir}/summary.json"), json)
+        .map_err(|e| format!("Failed to create {report_dir}/summary.json: {e}"))?;
+    println!("Combined test and coverage report written to {report_dir}");
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct Summary<'a> {
+    success: bool,
+    steps: &'a [StepResult],
+
+    /// Outputs the build step regenerated, 0 for a no-op build. Ninja only.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rebuilt: Option<usize>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tests: Option<TestSummary>,
+}
+
+pub fn write(path: &str, steps: &Steps, tests: Option<TestSummary>, rebuilt: Option<usize>) {
+    let summary = Summary { success: steps.status, steps: &steps.results, rebuilt, tests };
+    let json = serde_json::to_string_pretty(&summary).unwrap();
+    fs::write(path, json).unwrap_or_else(|_| panic!("Failed to create {path}"));
+}
@@ -0,0 +1,68 @@
+use std::{collections::BTreeMap, fs, path::Path};
+use serde::{Deserialize, Serialize};
+use super::diagnostic::Diagnostic;
+
+/// A diagnostic as the baseline matches it: line numbers shift with every
+/// edit, so only the file relative to the repo root, the check and the
+/// message are compared.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Key {
+    pub file: String,
+    pub check: String,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Entry {
+    #[serde(flatten)]
+    key: Key,
+    count: usize,
+}
+
+/// How often each accepted diagnostic occurs.
+pub type Baseline = BTreeMap<Key, usize>;
+
+pub fn from_diagnostics(diagnostics: &[Diagnostic], repo_root: &str) -> Baseline {
+    let mut baseline = Baseline::new();
+    for diag in diagnostics.iter().filter(|diag| diag.level != "note") {
+        let file = Path::new(&diag.file)
+            .strip_prefix(repo_root)
+            .map(|file| file.to_string_lossy().into_owned())
+            .unwrap_or_else(|_| diag.file.clone());
+        let key = Key {
+            file,
+            check: diag.flag.clone().unwrap_or_default(),
+            message: diag.message.clone(),
+        };
+        *baseline.entry(key).or_default() += 1;
+    }
+
+    baseline
+}
+
+pub fn write(path: &str, baseline: &Baseline) -> Result<(), String> {
+    let entries: Vec<Entry> = baseline
+        .iter()
+        .map(|(key, count)| Entry { key: key.clone(), count: *count })
+        .collect();
+    let json = serde_json::to_string_pretty(&entries).unwrap();
+    fs::write(path, json).map_err(|e| format!("Failed to write {path}: {e}"))
+}
+
+pub fn read(path: &str) -> Result<Baseline, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Failed to read {path}: {e}"))?;
+    let entries: Vec<Entry> = serde_json::from_str(&text).map_err(|e| format!("Failed to parse {path}: {e}"))?;
+    Ok(entries.into_iter().map(|entry| (entry.key, entry.count)).collect())
+}
+
+/// The diagnostics of `current` beyond what `baseline` accepts, with how
+/// many more times each occurs.
+pub fn new_diagnostics(current: &Baseline, baseline: &Baseline) -> Vec<(Key, usize)> {
+    current
+        .iter()
+        .filter_map(|(key, count)| {
+            let accepted = baseline.get(key).copied().unwrap_or_default();
+            (*count > accepted).then(|| (key.clone(), count - accepted))
+        })
+        .collect()
+}
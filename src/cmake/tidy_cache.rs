@@ -0,0 +1,88 @@
+use std::{
+    fs::{self, create_dir_all},
+    io,
+    path::Path,
+};
+use super::{compile_db, CmakeVars};
+use crate::hash::sha256_hex;
+
+/// Run clang-tidy one file at a time, reusing results stored in `cache_dir`.
+///
+/// Results are keyed by the file's content, the tidy config, the file's
+/// compile command and the clang-tidy binary, so changing any of those reruns
+/// the file. Headers aren't part of the key, so a header-only change needs the
+/// cache cleared. Fails on the first cache or ClangTidy directory I/O error.
+pub fn run(
+    cmds: &CmakeVars,
+    cache_dir: &str,
+    artifacts: &str,
+    cfg_loc: &str,
+    files: &[String],
+) -> io::Result<(Vec<u8>, bool)> {
+    create_dir_all(cache_dir)?;
+
+    // --tidy-apply-fixes applies every YAML under ClangTidy, so only this
+    // run's fixes may be there: older ones have offsets into old content.
+    let fixes_dir = format!("{artifacts}/ClangTidy/cached-fixes");
+    if Path::new(&fixes_dir).exists() {
+        fs::remove_dir_all(&fixes_dir)?;
+    }
+    create_dir_all(&fixes_dir)?;
+    let uncached_fixes = format!("{artifacts}/ClangTidy/clang-tidy-fixes.yaml");
+    if Path::new(&uncached_fixes).exists() {
+        fs::remove_file(&uncached_fixes)?;
+    }
+
+    let config = fs::read(cfg_loc).unwrap_or_default();
+    let entries = compile_db::read(artifacts).unwrap_or_default();
+
+    let mut stdout = Vec::new();
+    let mut success = true;
+    let (mut hits, mut misses) = (0, 0);
+    for file in files {
+        let key = cache_key(&cmds.clang_tidy_bin, &config, &entries, file);
+        let log_file = format!("{cache_dir}/{key}.log");
+        let fixes_file = format!("{cache_dir}/{key}.yaml");
+        let failed_file = format!("{cache_dir}/{key}.failed");
+
+        if Path::new(&log_file).exists() {
+            hits += 1;
+        } else {
+            misses += 1;
+            let output = super::run_tidy(cmds, artifacts, cfg_loc, &fixes_file, std::slice::from_ref(file));
+            if !output.status.success() {
+                fs::write(&failed_file, "")?;
+            }
+            // The log is written last as it marks the entry complete.
+            fs::write(&log_file, &output.stdout)?;
+        }
+
+        stdout.extend(fs::read(&log_file)?);
+        success &= !Path::new(&failed_file).exists();
+        if Path::new(&fixes_file).exists() {
+            fs::copy(&fixes_file, format!("{fixes_dir}/{key}.yaml"))?;
+        }
+    }
+
+    println!("Tidy cache: {hits} hits, {misses} misses");
+    Ok((stdout, success))
+}
+
+fn cache_key(tidy_bin: &str, config: &[u8], entries: &[compile_db::Entry], file: &str) -> String {
+    let canonical = fs::canonicalize(file).ok();
+    let compile_args = entries
+        .iter()
+        .find(|entry| fs::canonicalize(&entry.file).ok() == canonical)
+        .map(|entry| entry.args().join(" "))
+        .unwrap_or_default();
+
+    let mut data = tidy_bin.as_bytes().to_vec();
+    data.push(0);
+    data.extend(config);
+    data.push(0);
+    data.extend(compile_args.as_bytes());
+    data.push(0);
+    data.extend(fs::read(file).unwrap_or_default());
+
+    sha256_hex(&data)
+}
@@ -0,0 +1,66 @@
+use std::{
+    io,
+    process::{Child, Command, ExitStatus},
+    thread,
+    time::{Duration, Instant},
+};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Spawn `cmd` so `kill_tree` can reach everything it starts. On Unix that
+/// is a process group of its own, which also means a Ctrl-C in the terminal
+/// no longer reaches it, so only commands with a time limit get one.
+pub fn isolate(cmd: &mut Command) {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        cmd.process_group(0);
+    }
+    #[cfg(not(unix))]
+    let _ = cmd;
+}
+
+/// Wait for `child` for at most `limit`, killing it and what it started
+/// once the limit passes. None when it was killed.
+pub fn wait(child: &mut Child, limit: Duration) -> io::Result<Option<ExitStatus>> {
+    let start = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if start.elapsed() >= limit {
+            kill_tree(child);
+            child.wait()?;
+            return Ok(None);
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Kill `child` along with its own children, e.g. the git or curl a CMake
+/// FetchContent download runs, which would otherwise keep going and hold
+/// the captured output pipes open.
+#[cfg(unix)]
+fn kill_tree(child: &mut Child) {
+    // SAFETY: kill only sends a signal. `isolate` made the child a group
+    // leader, so the negated pid names its group.
+    if unsafe { libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL) } != 0 {
+        let _ = child.kill();
+    }
+}
+
+#[cfg(windows)]
+fn kill_tree(child: &mut Child) {
+    let killed = Command::new("taskkill")
+        .args(["/F", "/T", "/PID", &child.id().to_string()])
+        .output()
+        .is_ok_and(|output| output.status.success());
+    if !killed {
+        let _ = child.kill();
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn kill_tree(child: &mut Child) {
+    let _ = child.kill();
+}
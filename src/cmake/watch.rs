@@ -0,0 +1,94 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, IsTerminal},
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use crate::repo::is_cpp;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Modification times of the sources and CMake files under `root`.
+pub type Snapshot = BTreeMap<PathBuf, SystemTime>;
+
+/// Take a snapshot of `root`, leaving out `build_dir` and .git.
+pub fn snapshot(root: &str, build_dir: &str) -> Snapshot {
+    let build_dir = Path::new(build_dir).canonicalize().unwrap_or_else(|_| PathBuf::from(build_dir));
+    let mut snapshot = Snapshot::new();
+    walk(Path::new(root), &build_dir, &mut snapshot);
+    snapshot
+}
+
+/// Add the watched files under `dir` to `snapshot`, without descending into
+/// `build_dir`, .git or symlinked directories, so a poll only reads what it
+/// may report.
+fn walk(dir: &Path, build_dir: &Path, snapshot: &mut Snapshot) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+
+        if file_type.is_dir() {
+            let abs = path.canonicalize().unwrap_or_else(|_| path.clone());
+            if entry.file_name() != ".git" && abs != build_dir {
+                walk(&path, build_dir, snapshot);
+            }
+        } else if is_watched(&path) {
+            if let Ok(modified) = path.metadata().and_then(|meta| meta.modified()) {
+                snapshot.insert(path, modified);
+            }
+        }
+    }
+}
+
+fn is_watched(path: &Path) -> bool {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.is_file() && (name == "CMakeLists.txt" || name.ends_with(".cmake") || is_cpp(&name))
+}
+
+/// Poll until a watched file is added, removed or modified, then return
+/// those files and update `last`.
+pub fn wait_for_change(root: &str, build_dir: &str, last: &mut Snapshot) -> Vec<PathBuf> {
+    loop {
+        thread::sleep(POLL_INTERVAL);
+
+        let current = snapshot(root, build_dir);
+        let mut changed: Vec<PathBuf> = current
+            .iter()
+            .filter(|(path, modified)| last.get(*path) != Some(modified))
+            .map(|(path, _)| path.clone())
+            .collect();
+        changed.extend(last.keys().filter(|path| !current.contains_key(*path)).cloned());
+
+        if !changed.is_empty() {
+            *last = current;
+            return changed;
+        }
+    }
+}
+
+/// Print what triggered a rebuild, clearing the terminal first if asked to
+/// and stdout is one.
+pub fn print_header(changed: &[PathBuf], clear: bool) {
+    if clear && io::stdout().is_terminal() {
+        print!("\x1b[2J\x1b[H");
+    }
+
+    println!("[{}] Rebuilding, changed:", utc_time());
+    for path in changed {
+        println!("    {}", path.display());
+    }
+}
+
+/// The wall clock time as HH:MM:SS UTC.
+fn utc_time() -> String {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    format!("{:02}:{:02}:{:02} UTC", secs / 3600 % 24, secs / 60 % 60, secs % 60)
+}
@@ -0,0 +1,178 @@
+use std::{collections::BTreeMap, env, fs, path::Path, thread, time::Duration};
+use serde::Deserialize;
+
+/// Read from REPO_ROOT (or the current directory) when present.
+pub const CONFIG_FILE: &str = "cli_assist.toml";
+
+/// Steps a `[retries]` entry may name.
+const RETRY_STEPS: [&str; 7] = ["configure", "build", "target", "test", "coverage", "install", "deploy"];
+
+/// Retries beyond this are almost certainly a typo.
+const MAX_RETRIES: u32 = 10;
+
+/// Project settings from `cli_assist.toml`, e.g.
+///
+/// ```toml
+/// [retries]
+/// build = { count = 2, backoff_secs = 30 }
+/// test = { count = 1 }
+///
+/// [toolchain.clang-asan]
+/// description = "Clang with AddressSanitizer and UBSan"
+/// c_compiler = "clang"
+/// cxx_compiler = "clang++"
+/// sanitize = ["address", "undefined"]
+/// defines = { CMAKE_BUILD_TYPE = "Debug" }
+/// env = { CCACHE_SLOPPINESS = "time_macros" }
+/// ```
+///
+/// Command line flags override what the file sets, e.g. `cmake --retry test=0`.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub retries: BTreeMap<String, Retry>,
+
+    #[serde(default)]
+    pub toolchain: BTreeMap<String, Toolchain>,
+}
+
+/// How often to rerun a failed step, and how long to wait in between.
+#[derive(Deserialize, Debug, Default, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct Retry {
+    #[serde(default)]
+    pub count: u32,
+
+    #[serde(default)]
+    pub backoff_secs: u64,
+}
+
+/// A named set of compilers, sanitizers, cache variables and environment
+/// variables that `cmake --toolchain-profile NAME` configures with.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Toolchain {
+    /// One line shown by `cmake --list-variants`.
+    pub description: Option<String>,
+    pub c_compiler: Option<String>,
+    pub cxx_compiler: Option<String>,
+    /// Passed as CMAKE_TOOLCHAIN_FILE, relative to the source directory.
+    pub toolchain_file: Option<String>,
+    /// Names as `cmake --sanitize` takes them.
+    #[serde(default)]
+    pub sanitize: Vec<String>,
+    #[serde(default)]
+    pub defines: BTreeMap<String, String>,
+    /// Set for the configure, build, test and install commands.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+}
+
+impl Toolchain {
+    /// The `-D` arguments the profile expands to.
+    pub fn cmake_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        let known = [
+            ("CMAKE_C_COMPILER", &self.c_compiler),
+            ("CMAKE_CXX_COMPILER", &self.cxx_compiler),
+            ("CMAKE_TOOLCHAIN_FILE", &self.toolchain_file),
+        ];
+        for (var, value) in known {
+            if let Some(value) = value {
+                args.push(format!("-D{var}={value}"));
+            }
+        }
+        args.extend(self.defines.iter().map(|(var, value)| format!("-D{var}={value}")));
+
+        args
+    }
+}
+
+impl Config {
+    /// The retry policy of `step`, no retries unless configured.
+    pub fn retry(&self, step: &str) -> Retry {
+        self.retries.get(step).copied().unwrap_or_default()
+    }
+
+    /// The toolchain profile `name`, listing the defined ones if it isn't one.
+    pub fn toolchain(&self, name: &str) -> Result<Toolchain, String> {
+        if let Some(toolchain) = self.toolchain.get(name) {
+            return Ok(toolchain.clone());
+        }
+
+        match self.toolchain.is_empty() {
+            true => Err(format!("Unknown toolchain profile '{name}', {CONFIG_FILE} defines none")),
+            false => Err(format!(
+                "Unknown toolchain profile '{name}', expected one of {}",
+                self.toolchain.keys().cloned().collect::<Vec<_>>().join(", ")
+            )),
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        for (step, retry) in &self.retries {
+            if !RETRY_STEPS.contains(&step.as_str()) {
+                return Err(format!(
+                    "[retries] {step}: unknown step, expected one of {}",
+                    RETRY_STEPS.join(", ")
+                ));
+            }
+            if retry.count > MAX_RETRIES {
+                return Err(format!("[retries] {step}: count must be at most {MAX_RETRIES}"));
+            }
+        }
+
+        for name in self.toolchain.keys() {
+            let valid = name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+            if name.is_empty() || !valid {
+                return Err(format!("[toolchain.{name}]: names may only use letters, digits, '-' and '_'"));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Retry {
+    /// Run `step`, rerunning it up to `count` more times while it fails.
+    pub fn run(&self, name: &str, mut step: impl FnMut() -> bool) -> bool {
+        let mut success = step();
+        for attempt in 1..=self.count {
+            if success {
+                break;
+            }
+
+            println!("{name} failed, retrying in {}s ({attempt}/{})", self.backoff_secs, self.count);
+            thread::sleep(Duration::from_secs(self.backoff_secs));
+            success = step();
+        }
+
+        success
+    }
+}
+
+/// Parse a `STEP=COUNT` retry override.
+pub fn parse_retry(text: &str) -> Result<(String, u32), String> {
+    let (step, count) = text.split_once('=').ok_or("expected STEP=COUNT, e.g. test=2")?;
+    let count: u32 = count.parse().map_err(|_| format!("invalid retry count '{count}'"))?;
+    if !RETRY_STEPS.contains(&step) {
+        return Err(format!("unknown step '{step}', expected one of {}", RETRY_STEPS.join(", ")));
+    }
+
+    Ok((step.to_string(), count))
+}
+
+/// Load the config file, an empty config if there is none.
+pub fn load() -> Result<Config, String> {
+    let root = env::var("REPO_ROOT").unwrap_or_else(|_| ".".to_string());
+    let path = Path::new(&root).join(CONFIG_FILE);
+    if !path.exists() {
+        return Ok(Config::default());
+    }
+
+    let text = fs::read_to_string(&path).map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+    let config: Config = toml::from_str(&text).map_err(|e| format!("Failed to parse {}: {e}", path.display()))?;
+    config.validate()?;
+    Ok(config)
+}
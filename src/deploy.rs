@@ -0,0 +1,119 @@
+use std::{
+    collections::HashMap,
+    fs::{self, create_dir_all},
+    path::Path,
+};
+use clap::Parser;
+use crate::{config, hash::sha256_hex};
+
+const MANIFEST: &str = "SHA256SUMS";
+
+#[derive(Parser, Debug)]
+pub struct DeployVars {
+    /// Directory to upload the artifacts and their SHA256SUMS manifest to
+    #[clap(long, value_name = "DIR", required_unless_present = "verify")]
+    dest: Option<String>,
+
+    /// Check the files in DIR against its SHA256SUMS manifest instead of deploying
+    #[clap(long, value_name = "DIR", conflicts_with = "dest")]
+    verify: Option<String>,
+
+    /// Artifacts to deploy
+    #[clap(required_unless_present = "verify")]
+    artifacts: Vec<String>,
+}
+
+pub fn process(cmds: DeployVars) {
+    let status = match (cmds.verify, cmds.dest) {
+        (Some(dir), _) => verify(&dir),
+        (None, Some(dest)) => match config::load() {
+            Ok(config) => config.retry("deploy").run("deploy", || deploy(&dest, &cmds.artifacts)),
+            Err(e) => {
+                eprintln!("{e}");
+                false
+            }
+        },
+        (None, None) => false,
+    };
+
+    println!("Deploy finished with: {status}");
+}
+
+/// Copy each artifact into `dest` and write a `sha256sum` compatible manifest
+/// next to them. Artifacts are uploaded by file name, so two with the same
+/// name fail the deploy before anything is copied.
+fn deploy(dest: &str, artifacts: &[String]) -> bool {
+    let mut names = HashMap::new();
+    for artifact in artifacts {
+        let Some(name) = Path::new(artifact).file_name() else {
+            eprintln!("Artifact {artifact} has no file name");
+            return false;
+        };
+        if let Some(other) = names.insert(name, artifact) {
+            eprintln!("{other} and {artifact} would both be uploaded as {}", name.to_string_lossy());
+            return false;
+        }
+    }
+
+    if let Err(e) = create_dir_all(dest) {
+        eprintln!("Failed to create {dest}: {e}");
+        return false;
+    }
+
+    let mut manifest = String::new();
+    for artifact in artifacts {
+        let data = match fs::read(artifact) {
+            Ok(data) => data,
+            Err(e) => {
+                eprintln!("Failed to read {artifact}: {e}");
+                return false;
+            }
+        };
+        let name = Path::new(artifact).file_name().unwrap_or_default().to_string_lossy();
+
+        if let Err(e) = fs::write(format!("{dest}/{name}"), &data) {
+            eprintln!("Failed to upload {artifact}: {e}");
+            return false;
+        }
+        manifest.push_str(&format!("{}  {name}\n", sha256_hex(&data)));
+        println!("Uploaded {name}");
+    }
+
+    if let Err(e) = fs::write(format!("{dest}/{MANIFEST}"), manifest) {
+        eprintln!("Failed to upload {MANIFEST}: {e}");
+        return false;
+    }
+
+    true
+}
+
+fn verify(dir: &str) -> bool {
+    let manifest_path = format!("{dir}/{MANIFEST}");
+    let Ok(manifest) = fs::read_to_string(&manifest_path) else {
+        eprintln!("{manifest_path} not found");
+        return false;
+    };
+
+    let mut status = true;
+    for line in manifest.lines().filter(|line| !line.trim().is_empty()) {
+        let Some((expected, name)) = line.split_once("  ") else {
+            eprintln!("Malformed manifest line: {line}");
+            status = false;
+            continue;
+        };
+
+        match fs::read(format!("{dir}/{name}")) {
+            Ok(data) if sha256_hex(&data) == expected => println!("{name}: OK"),
+            Ok(data) => {
+                eprintln!("{name}: MISMATCH, expected {expected} got {}", sha256_hex(&data));
+                status = false;
+            }
+            Err(_) => {
+                eprintln!("{name}: MISSING");
+                status = false;
+            }
+        }
+    }
+
+    status
+}
@@ -1,4 +1,9 @@
 pub mod cmake;
+pub mod deploy;
+pub mod repo;
+
+mod config;
+mod hash;
 
 use clap::{Parser, Subcommand};
 
@@ -13,7 +18,13 @@ struct Args {
 #[derive(Subcommand, Debug)]
 pub enum Commands {
     // CMake controls
-    Cmake(cmake::CmakeVars),
+    Cmake(Box<cmake::CmakeVars>),
+
+    // Repository helpers
+    Repo(repo::RepoVars),
+
+    // Artifact deployment
+    Deploy(deploy::DeployVars),
 }
 
 pub fn run() {
@@ -21,7 +32,13 @@ pub fn run() {
 
     match cmds {
         Commands::Cmake(v) => {
-            cmake::process(v);
+            cmake::process(*v);
+        }
+        Commands::Repo(v) => {
+            repo::process(v);
+        }
+        Commands::Deploy(v) => {
+            deploy::process(v);
         }
     }
 }
@@ -1,5 +1,3 @@
-use cli_assist;
-
 fn main() {
     cli_assist::run()
 }
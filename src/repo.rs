@@ -0,0 +1,254 @@
+use std::{
+    env,
+    fs::remove_dir_all,
+    path::Path,
+    process::Command,
+    time::{Duration, Instant},
+};
+use clap::{Parser, Subcommand};
+
+#[derive(Parser, Debug)]
+pub struct RepoVars {
+    #[command(subcommand)]
+    cmd: RepoCommands,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum RepoCommands {
+    /// Print a version derived from git, as `<tag>+<commits>.g<sha>[.dirty]`
+    Version,
+
+    /// Print the files changed since GIT_REF, one per line
+    ChangedFiles {
+        /// Commit, branch or tag to compare against, e.g. origin/main
+        git_ref: String,
+
+        /// Only print C and C++ sources and headers
+        #[clap(long, action)]
+        cpp: bool,
+    },
+
+    /// Time a clean configure and build of two refs and report the
+    /// difference. Builds in BUILD_DIR-bench and needs a clean working tree.
+    BenchBuild {
+        /// Baseline commit, branch or tag
+        ref_a: String,
+
+        /// Commit, branch or tag to compare with the baseline
+        ref_b: String,
+    },
+}
+
+/// Extensions `changed-files --cpp` keeps.
+pub const CPP_EXTENSIONS: [&str; 9] = ["c", "cc", "cpp", "cxx", "h", "hh", "hpp", "hxx", "ipp"];
+
+pub fn process(cmds: RepoVars) {
+    match cmds.cmd {
+        RepoCommands::Version => match git_version() {
+            Some(version) => println!("{version}"),
+            None => eprintln!("Failed to derive a version, is this a git repository?"),
+        },
+        RepoCommands::ChangedFiles { git_ref, cpp } => match changed_files(&git_ref) {
+            Some(files) => {
+                for file in files.iter().filter(|file| !cpp || is_cpp(file)) {
+                    println!("{file}");
+                }
+            }
+            None => eprintln!("Failed to diff against {git_ref}, is it a valid ref?"),
+        },
+        RepoCommands::BenchBuild { ref_a, ref_b } => {
+            let status = bench_build(&ref_a, &ref_b);
+            println!("Bench build finished with: {status}");
+        }
+    }
+}
+
+fn bench_build(ref_a: &str, ref_b: &str) -> bool {
+    // Untracked files survive checkouts, and BUILD_DIR-bench is one of them.
+    if !git(&["status", "--porcelain", "--untracked-files=no"]).is_some_and(|status| status.is_empty()) {
+        eprintln!("bench-build checks out other refs, commit or stash your changes first");
+        return false;
+    }
+
+    let Some(root) = git(&["rev-parse", "--show-toplevel"]) else {
+        eprintln!("Not a git repository");
+        return false;
+    };
+    let build_dir = format!("{}-bench", env::var("BUILD_DIR").expect("BUILD_DIR environment variable not set"));
+
+    // Return to the branch rather than detaching at its commit.
+    let original = git(&["symbolic-ref", "--short", "-q", "HEAD"]).or_else(|| git(&["rev-parse", "HEAD"]));
+    let Some(original) = original else {
+        eprintln!("Failed to resolve HEAD");
+        return false;
+    };
+
+    // Resolve both up front, relative refs like HEAD~1 move with checkouts.
+    let resolve = |git_ref: &str| git(&["rev-parse", "--verify", "-q", &format!("{git_ref}^{{commit}}")]);
+    let (Some(commit_a), Some(commit_b)) = (resolve(ref_a), resolve(ref_b)) else {
+        eprintln!("Failed to resolve {ref_a} and {ref_b} to commits");
+        return false;
+    };
+
+    let time_a = time_build(ref_a, &commit_a, &root, &build_dir);
+    let time_b = time_a.and_then(|_| time_build(ref_b, &commit_b, &root, &build_dir));
+
+    if git(&["checkout", "-q", original.as_str()]).is_none() {
+        eprintln!("Failed to check out {original} again");
+        return false;
+    }
+
+    let (Some(time_a), Some(time_b)) = (time_a, time_b) else {
+        return false;
+    };
+
+    let delta = time_b.as_secs_f64() - time_a.as_secs_f64();
+    println!("{ref_a}: {:.1}s", time_a.as_secs_f64());
+    println!("{ref_b}: {:.1}s", time_b.as_secs_f64());
+    println!("Delta: {delta:+.1}s ({:+.1}%)", delta / time_a.as_secs_f64() * 100.0);
+    true
+}
+
+/// Check out `commit` and time a configure and build from scratch, None if
+/// any of it failed.
+fn time_build(git_ref: &str, commit: &str, root: &str, build_dir: &str) -> Option<Duration> {
+    if git(&["checkout", "-q", commit]).is_none() {
+        eprintln!("Failed to check out {git_ref}");
+        return None;
+    }
+
+    // Fail rather than panic from here on, so bench_build still checks the
+    // original ref out again.
+    if Path::new(build_dir).exists() {
+        if let Err(e) = remove_dir_all(build_dir) {
+            eprintln!("Failed to remove {build_dir}: {e}");
+            return None;
+        }
+    }
+
+    let run = |cmd: &mut Command| match cmd.status() {
+        Ok(status) => status.success(),
+        Err(e) => {
+            eprintln!("Failed to run cmake: {e}");
+            false
+        }
+    };
+
+    println!("Building {git_ref}");
+    let start = Instant::now();
+    let success = run(Command::new("cmake")
+        .arg("-S")
+        .arg(root)
+        .arg("-B")
+        .arg(build_dir)
+        .arg("-G")
+        .arg("Ninja"))
+        && run(Command::new("cmake").arg("--build").arg(build_dir).arg("--parallel"));
+
+    if !success {
+        eprintln!("Building {git_ref} failed");
+        return None;
+    }
+
+    Some(start.elapsed())
+}
+
+/// Files added, copied, modified or renamed since the merge base of
+/// `git_ref` and HEAD, including uncommitted changes, relative to the
+/// repository root. Deleted files are left out.
+pub fn changed_files(git_ref: &str) -> Option<Vec<String>> {
+    let base = git(&["merge-base", git_ref, "HEAD"])?;
+    let diff = git(&["diff", "--name-only", "--diff-filter=ACMR", base.as_str()])?;
+    Some(diff.lines().map(str::to_string).collect())
+}
+
+pub fn is_cpp(file: &str) -> bool {
+    Path::new(file)
+        .extension()
+        .is_some_and(|ext| CPP_EXTENSIONS.iter().any(|cpp| ext == *cpp))
+}
+
+/// HEAD's place in the history: the tag it descends from with a leading `v`
+/// dropped (`0.0.0` when nothing is tagged), the commits since that tag, the
+/// short sha and whether the working tree is dirty.
+struct Described {
+    tag: String,
+    since: String,
+    sha: String,
+    dirty: bool,
+}
+
+fn describe() -> Option<Described> {
+    let sha = git(&["rev-parse", "--short", "HEAD"])?;
+    let (tag, since) = match git(&["describe", "--tags", "--abbrev=0"]) {
+        Some(tag) => {
+            let range = format!("{tag}..HEAD");
+            let since = git(&["rev-list", "--count", range.as_str()])?;
+            (tag.trim_start_matches('v').to_string(), since)
+        }
+        None => ("0.0.0".to_string(), git(&["rev-list", "--count", "HEAD"])?),
+    };
+    let dirty = !git(&["status", "--porcelain"])?.is_empty();
+
+    Some(Described { tag, since, sha, dirty })
+}
+
+/// Describe HEAD as `<tag>+<commits since tag>.g<short sha>[.dirty]`.
+pub fn git_version() -> Option<String> {
+    let described = describe()?;
+    let dirty = match described.dirty {
+        true => ".dirty",
+        false => "",
+    };
+
+    Some(format!("{}+{}.g{}{dirty}", described.tag, described.since, described.sha))
+}
+
+/// HEAD's version in the numeric form project(VERSION) accepts, see
+/// `numeric_version`.
+pub fn git_numeric_version() -> Result<String, String> {
+    let described = describe().ok_or("Failed to derive a version, is this a git repository?")?;
+    numeric_version(&described.tag, &described.since)
+}
+
+/// `tag` padded to MAJOR.MINOR.PATCH with `since` as the tweak, so every
+/// commit after a tag gets a distinct N.N.N.N version. Fails when the tag
+/// isn't one to three numbers separated by dots.
+pub fn numeric_version(tag: &str, since: &str) -> Result<String, String> {
+    let mut parts: Vec<&str> = tag.split('.').collect();
+    if parts.len() > 3 || parts.iter().any(|part| part.is_empty() || !part.bytes().all(|b| b.is_ascii_digit())) {
+        return Err(format!("Tag {tag} isn't a numeric MAJOR[.MINOR[.PATCH]] version"));
+    }
+    parts.resize(3, "0");
+
+    Ok(format!("{}.{since}", parts.join(".")))
+}
+
+/// Run git and return its trimmed stdout, or None if it failed.
+pub fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::numeric_version;
+
+    #[test]
+    fn numeric_version_pads_the_tag_and_adds_the_commits_as_the_tweak() {
+        assert_eq!(numeric_version("1", "0"), Ok("1.0.0.0".to_string()));
+        assert_eq!(numeric_version("1.2", "7"), Ok("1.2.0.7".to_string()));
+        assert_eq!(numeric_version("1.2.3", "42"), Ok("1.2.3.42".to_string()));
+    }
+
+    #[test]
+    fn numeric_version_rejects_tags_cmake_cannot_parse() {
+        for tag in ["1.2.3-rc1", "1.2.3.4", "release", "1..2", ""] {
+            assert!(numeric_version(tag, "0").is_err(), "{tag} was accepted");
+        }
+    }
+}